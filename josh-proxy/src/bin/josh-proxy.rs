@@ -691,7 +691,14 @@ async fn run_proxy() -> josh::JoshResult<i32> {
     );
 
     josh_proxy::create_repo(&local)?;
-    josh::cache::load(&local)?;
+    // Plugins run executables on the server, so only those explicitly listed are allowed
+    josh::filter::allow_plugins(
+        &ARGS
+            .values_of("plugin")
+            .map(|x| x.map(String::from).collect::<Vec<_>>())
+            .unwrap_or_default(),
+    );
+    josh::cache::load_backend(&local, ARGS.value_of("cache-backend").unwrap_or("sled"))?;
 
     let proxy_service = Arc::new(JoshProxyService {
         port,
@@ -722,6 +729,8 @@ async fn run_proxy() -> josh::JoshResult<i32> {
             let s = _s;
 
             async move {
+                // Filters are held for the whole request
+                let _pin = josh::filter::pin();
                 let r = if let Ok(req_auth) = josh_proxy::auth::strip_auth(_req) {
                     match call_service(proxy_service, req_auth)
                         .instrument(s.clone())
@@ -792,13 +801,24 @@ async fn run_polling(serv: Arc<JoshProxyService>) -> josh::JoshResult<()> {
 }
 
 async fn run_housekeeping(local: std::path::PathBuf) -> josh::JoshResult<()> {
+    let cache_max_age: u64 = ARGS.value_of("cache-max-age").unwrap_or("30").parse()?;
+    let cache_max_age = std::time::Duration::from_secs(cache_max_age * 24 * 60 * 60);
     let mut i: usize = 0;
     loop {
         let local = local.clone();
         tokio::task::spawn_blocking(move || {
-            josh::housekeeping::run(&local, (i % 60 == 0) && ARGS.is_present("gc"))
+            josh::housekeeping::run(
+                &local,
+                (i % 60 == 0) && ARGS.is_present("gc"),
+                cache_max_age,
+            )
         })
         .await??;
+        if i % 60 == 0 {
+            // Frees filters that have not been used for an hour
+            let removed = josh::filter::collect();
+            tracing::debug!("collected {} filters", removed);
+        }
         tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
         i += 1;
     }
@@ -813,7 +833,7 @@ fn make_app() -> clap::App<'static> {
             clap::Arg::new("gc")
                 .long("gc")
                 .takes_value(false)
-                .help("Run git gc in maintanance"),
+                .help("Run git gc and prune the cache in maintanance"),
         )
         .arg(
             clap::Arg::new("require-auth")
@@ -845,6 +865,27 @@ fn make_app() -> clap::App<'static> {
                 .takes_value(true)
                 .help("Duration between forced cache refresh"),
         )
+        .arg(
+            clap::Arg::new("cache-max-age")
+                .long("cache-max-age")
+                .takes_value(true)
+                .help(
+                    "Days after which results of unused filters are removed by --gc (default 30)",
+                ),
+        )
+        .arg(
+            clap::Arg::new("plugin")
+                .long("plugin")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Allow the :plugin filter to run the plugin with this name"),
+        )
+        .arg(
+            clap::Arg::new("cache-backend")
+                .long("cache-backend")
+                .takes_value(true)
+                .help("Storage of the cache: sled (default), sqlite or memory"),
+        )
 }
 
 fn parse_args() -> clap::ArgMatches {
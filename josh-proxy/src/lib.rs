@@ -132,6 +132,16 @@ pub fn process_repo_update(repo_update: RepoUpdate) -> josh::JoshResult<String>
         //};
 
         let filterobj = josh::filter::parse(&repo_update.filter_spec)?;
+        if let Err(diagnostics) = josh::filter::check_reversible(filterobj) {
+            return Err(josh::josh_error(&format!(
+                "pushing to this view is not possible:\n{}",
+                diagnostics
+                    .iter()
+                    .map(|d| format!("  {}", d))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )));
+        }
         let new_oid = git2::Oid::from_str(new)?;
         let backward_new_oid = {
             tracing::debug!("=== MORE");
@@ -227,7 +237,7 @@ pub fn process_repo_update(repo_update: RepoUpdate) -> josh::JoshResult<String>
             warning_str += "\nwarnings:";
             for warn in warnings {
                 warning_str += "\n";
-                warning_str.push_str(warn);
+                warning_str.push_str(&warn.to_string());
             }
         }
 
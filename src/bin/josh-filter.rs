@@ -69,6 +69,34 @@ fn make_app() -> clap::App<'static> {
                 .help("Don't load cache")
                 .short('n'),
         )
+        .arg(
+            clap::Arg::new("cache-backend")
+                .help("Storage of the cache: sled (default), sqlite or memory")
+                .long("cache-backend")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::new("plugin")
+                .help("Allow the :plugin filter to run the plugin with this name")
+                .long("plugin")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            clap::Arg::new("plugin-timeout")
+                .help("Kill plugins that run for longer than the given number of seconds")
+                .long("plugin-timeout")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::new("cache-gc")
+                .help(
+                    "Remove cache entries of filters unused for longer than the given number \
+                     of seconds or for commits unreachable from any reference and exit",
+                )
+                .long("cache-gc")
+                .takes_value(true),
+        )
         .arg(
             clap::Arg::new("pack")
                 .help("Write a packfile instead of loose objects")
@@ -92,6 +120,12 @@ fn make_app() -> clap::App<'static> {
                 .short('m')
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::new("diff-filter")
+                .long("diff-filter")
+                .help("Show the paths selected differently by another filter and exit")
+                .takes_value(true),
+        )
         .arg(clap::Arg::new("reverse").long("reverse"))
         .arg(
             clap::Arg::new("check-permission")
@@ -147,6 +181,13 @@ fn run_filter(args: Vec<String>) -> josh::JoshResult<i32> {
         .and_then(|f| read_to_string(f).ok())
         .unwrap_or(specstr.to_string());
 
+    if let Some(plugins) = args.values_of("plugin") {
+        josh::filter::allow_plugins(&plugins.map(String::from).collect::<Vec<_>>());
+    }
+    if let Some(timeout) = args.value_of("plugin-timeout") {
+        josh::filter::set_plugin_timeout(std::time::Duration::from_secs(timeout.parse()?));
+    }
+
     let mut filterobj = josh::filter::parse(&specstr)?;
 
     if args.is_present("squash") {
@@ -158,14 +199,25 @@ fn run_filter(args: Vec<String>) -> josh::JoshResult<i32> {
             "{}",
             josh::filter::pretty(filterobj, if args.is_present("file") { 0 } else { 4 })
         );
+        if let Err(diagnostics) = josh::filter::check_reversible(filterobj) {
+            eprintln!("Warning: the filter is not reversible");
+            for d in diagnostics {
+                eprintln!("  {}", d);
+            }
+        }
         return Ok(0);
     }
 
     let repo = git2::Repository::open_from_env()?;
-    if !args.is_present("no-cache") {
-        josh::cache::load(repo.path())?;
-    }
-    let transaction = josh::cache::Transaction::new(repo, None);
+    josh::cache::load_backend(
+        repo.path(),
+        if args.is_present("no-cache") {
+            "memory"
+        } else {
+            args.value_of("cache-backend").unwrap_or("sled")
+        },
+    )?;
+    let transaction = josh::cache::Transaction::new(repo, None)?;
     let repo = transaction.repo();
 
     let odb = repo.odb()?;
@@ -181,7 +233,9 @@ fn run_filter(args: Vec<String>) -> josh::JoshResult<i32> {
             rs_tracing::close_trace_file!();
         }
         if args.is_present("cache-stats") {
-            josh::cache::print_stats();
+            if let Err(josh::JoshError(e)) = josh::cache::print_stats() {
+                eprintln!("ERROR: {}", e);
+            }
         }
         if let Some(mempack) = mp {
             let mut buf = git2::Buf::new();
@@ -196,6 +250,27 @@ fn run_filter(args: Vec<String>) -> josh::JoshResult<i32> {
 
     let input_ref = args.value_of("input").unwrap();
 
+    if let Some(other) = args.value_of("diff-filter") {
+        let tree = repo.revparse_single(input_ref)?.peel_to_tree()?;
+        let other = josh::filter::parse(other)?;
+        for change in josh::filter::diff_selection(&transaction, filterobj, other, tree)? {
+            println!("{}", change);
+        }
+        return Ok(0);
+    }
+
+    if let Some(max_age) = args.value_of("cache-gc") {
+        let stats = josh::cache::gc(
+            &transaction,
+            std::time::Duration::from_secs(max_age.parse()?),
+        )?;
+        println!(
+            "Removed {} entries ({} bytes of keys and values), including all of {} unused filters",
+            stats.entries, stats.bytes, stats.filters
+        );
+        return Ok(0);
+    }
+
     if args.is_present("discover") {
         let r = repo.revparse_single(input_ref)?;
         let hs = josh::housekeeping::find_all_workspaces_and_subdirectories(&r.peel_to_tree()?)?;
@@ -368,6 +443,7 @@ fn run_filter(args: Vec<String>) -> josh::JoshResult<i32> {
         );
     }
 
+    josh::cache::flush()?;
     Ok(0)
 }
 
@@ -1,45 +1,218 @@
 use super::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
 
-const VERSION: u64 = 9;
+mod backend;
+pub use backend::{CacheBackend, MemoryBackend, SledBackend, SqliteBackend};
+
+const VERSION: u64 = 10;
+
+/// Tree storing when the commits of each filter were last used, by name of their tree
+const ACCESS: &str = "_access";
+
+/// Tree storing the `FilterStats` of each filter, by name of its tree
+const STATS: &str = "_stats";
+
+/// Trees of intermediate results, these are not listed by `print_stats`
+const INTERMEDIATE: &[&str] = &["_apply", "_glob", "_populate", "_subtract", "_unapply"];
 
 lazy_static! {
-    static ref DB: std::sync::Mutex<Option<sled::Db>> = std::sync::Mutex::new(None);
+    static ref BACKEND: std::sync::Mutex<Option<Arc<dyn CacheBackend>>> =
+        std::sync::Mutex::new(None);
     static ref REF_CACHE: std::sync::Mutex<HashMap<git2::Oid, HashMap<git2::Oid, git2::Oid>>> =
         std::sync::Mutex::new(HashMap::new());
-    static ref POPULATE_MAP: std::sync::Mutex<HashMap<(git2::Oid, git2::Oid), git2::Oid>> =
-        std::sync::Mutex::new(HashMap::new());
-    static ref GLOB_MAP: std::sync::Mutex<HashMap<(git2::Oid, git2::Oid), git2::Oid>> =
+    /// Statistics of the filters, read from the `STATS` tree when first needed
+    static ref FILTER_STATS: std::sync::Mutex<Option<Stats>> = std::sync::Mutex::new(None);
+    /// Uses of filters not yet written to the `ACCESS` tree, see `flush`
+    static ref ACCESSED: std::sync::Mutex<HashMap<String, u64>> =
         std::sync::Mutex::new(HashMap::new());
 }
 
 pub fn load(path: &std::path::Path) -> JoshResult<()> {
-    *DB.lock()? = Some(
-        sled::Config::default()
-            .path(path.join(format!("josh/{}/sled/", VERSION)))
-            .flush_every_ms(Some(200))
-            .open()?,
-    );
+    load_backend(path, "sled")
+}
+
+/// Load the cache stored in `path` with the backend `name`: "sled", "sqlite" or "memory"
+pub fn load_backend(path: &std::path::Path, name: &str) -> JoshResult<()> {
+    let path = path.join(format!("josh/{}", VERSION));
+    let backend: Arc<dyn CacheBackend> = match name {
+        "sled" => Arc::new(SledBackend::open(&path.join("sled"))?),
+        "sqlite" => Arc::new(SqliteBackend::open(&path.join("cache.sqlite"))?),
+        "memory" => Arc::new(MemoryBackend::default()),
+        _ => return Err(josh_error(&format!("unknown cache backend: {:?}", name))),
+    };
+    *BACKEND.lock()? = Some(backend);
+    *FILTER_STATS.lock()? = None;
     Ok(())
 }
 
-pub fn print_stats() {
-    let d = DB.lock().unwrap();
-    let db = d.as_ref().unwrap();
-    db.flush().unwrap();
+/// Statistics about the results of a filter, see `Transaction::stats`
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FilterStats {
+    /// Number of results stored in the cache
+    pub results: usize,
+    /// Number of lookups that found no result
+    pub misses: usize,
+}
+
+impl FilterStats {
+    /// Share of lookups that are expected to find no result
+    pub fn miss_rate(&self) -> f64 {
+        if self.results == 0 {
+            return 1.0;
+        }
+        self.misses as f64 / (self.results + self.misses) as f64
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        [
+            (self.results as u64).to_le_bytes(),
+            (self.misses as u64).to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> FilterStats {
+        use std::convert::TryInto;
+
+        let number = |i: usize| {
+            bytes
+                .get(i * 8..i * 8 + 8)
+                .and_then(|x| x.try_into().ok())
+                .map(u64::from_le_bytes)
+                .unwrap_or(0) as usize
+        };
+        FilterStats {
+            results: number(0),
+            misses: number(1),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    /// By name of the tree storing the results
+    trees: BTreeMap<String, FilterStats>,
+    /// Trees whose statistics were changed since they were written to the backend
+    changed: HashSet<String>,
+    /// Parsed names of the trees, see `Transaction::cached_filters`
+    filters: HashMap<String, filter::Filter>,
+}
+
+impl Stats {
+    fn record(&mut self, name: &str, filter: filter::Filter, f: impl FnOnce(&mut FilterStats)) {
+        f(self.trees.entry(name.to_owned()).or_default());
+        if !self.changed.contains(name) {
+            self.changed.insert(name.to_owned());
+        }
+        if !self.filters.contains_key(name) {
+            self.filters.insert(name.to_owned(), filter);
+        }
+    }
+}
+
+fn with_stats<T>(f: impl FnOnce(&mut Stats) -> T) -> JoshResult<T> {
+    let mut stats = FILTER_STATS.lock()?;
+    if stats.is_none() {
+        let mut loaded = Stats::default();
+        for (name, value) in backend()?.entries(STATS)? {
+            loaded
+                .trees
+                .insert(String::from_utf8(name)?, FilterStats::from_bytes(&value));
+        }
+        *stats = Some(loaded);
+    }
+    let stats = stats
+        .as_mut()
+        .ok_or_else(|| josh_error("filter stats not loaded"))?;
+    Ok(f(stats))
+}
+
+/// Count the results stored for each filter. Depending on the backend this needs to read
+/// the whole cache, so it is only done by `housekeeping::run` and `gc`, in between the
+/// counts are increased when results are stored.
+pub fn update_stats() -> JoshResult<()> {
+    let backend = backend()?;
+    let mut sizes = HashMap::new();
+    for name in backend.trees()? {
+        if !name.starts_with('_') {
+            let len = backend.len(&name)?;
+            sizes.insert(name, len);
+        }
+    }
+    with_stats(|stats| {
+        stats.changed.extend(stats.trees.keys().cloned());
+        stats.changed.extend(sizes.keys().cloned());
+        stats.trees.retain(|name, _| sizes.contains_key(name));
+        for (name, len) in sizes {
+            stats.trees.entry(name).or_default().results = len;
+        }
+    })?;
+    write_stats(&*backend)
+}
+
+/// Write the statistics changed since the last call to the `STATS` tree
+fn write_stats(backend: &dyn CacheBackend) -> JoshResult<()> {
+    let changed = with_stats(|stats| {
+        std::mem::take(&mut stats.changed)
+            .into_iter()
+            .map(|name| {
+                let value = stats.trees.get(&name).copied();
+                (name, value)
+            })
+            .collect::<Vec<_>>()
+    })?;
+    for (name, value) in changed {
+        match value {
+            Some(value) => backend.insert(STATS, name.as_bytes(), &value.to_bytes())?,
+            None => backend.remove(STATS, name.as_bytes())?,
+        }
+    }
+    Ok(())
+}
+
+/// The backend loaded with `load` or `load_backend`
+fn backend() -> JoshResult<Arc<dyn CacheBackend>> {
+    BACKEND
+        .lock()?
+        .clone()
+        .ok_or_else(|| josh_error("cache not loaded"))
+}
+
+/// Persist everything inserted so far. The cache is never dropped, so without this, writes
+/// done just before the process exits may be lost.
+pub fn flush() -> JoshResult<()> {
+    let backend = backend()?;
+    write_access(&*backend)?;
+    write_stats(&*backend)?;
+    backend.flush()
+}
+
+/// Record the uses of filters collected by `Transaction::touch`
+fn write_access(backend: &dyn CacheBackend) -> JoshResult<()> {
+    let accessed = std::mem::take(&mut *ACCESSED.lock()?);
+    for (name, time) in accessed {
+        backend.insert(ACCESS, name.as_bytes(), &time.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn print_stats() -> JoshResult<()> {
+    let backend = backend()?;
+    backend.flush()?;
     log::debug!("Trees:");
     let mut v = vec![];
-    for name in db.tree_names() {
-        let name = String::from_utf8(name.to_vec()).unwrap();
-        let t = db.open_tree(&name).unwrap();
-        if !t.is_empty() {
-            let name = if name.contains("SUBTRACT") || name.starts_with('_') {
-                name.clone()
-            } else {
-                filter::pretty(filter::parse(&name).unwrap(), 4)
-            };
-            v.push((t.len(), name));
+    for name in backend.trees()? {
+        if name == ACCESS || name == STATS || INTERMEDIATE.contains(&name.as_str()) {
+            continue;
         }
+        let len = backend.len(&name)?;
+        let name = if name.contains("SUBTRACT") || name.starts_with('_') {
+            name.clone()
+        } else {
+            filter::pretty(filter::parse(&name)?, 4)
+        };
+        v.push((len, name));
     }
 
     v.sort();
@@ -47,39 +220,46 @@ pub fn print_stats() {
     for (len, name) in v.iter() {
         println!("[{}] {}", len, name);
     }
+    Ok(())
 }
 
 #[allow(unused)]
 struct Transaction2 {
     commit_map: HashMap<git2::Oid, HashMap<git2::Oid, git2::Oid>>,
-    apply_map: HashMap<git2::Oid, HashMap<git2::Oid, git2::Oid>>,
-    subtract_map: HashMap<(git2::Oid, git2::Oid), git2::Oid>,
-    unapply_map: HashMap<git2::Oid, HashMap<git2::Oid, git2::Oid>>,
-    sled_trees: HashMap<git2::Oid, sled::Tree>,
-    path_tree: sled::Tree,
-    invert_tree: sled::Tree,
-    trigram_index_tree: sled::Tree,
+    /// Results stored in the trees of intermediate results like `_apply`, by tree and key.
+    /// These are looked up very often, so they are written through to the backend and
+    /// kept here as well.
+    pairs: HashMap<(&'static str, git2::Oid, git2::Oid), git2::Oid>,
+    /// Names of the trees storing the commits of each filter
+    tree_names: HashMap<git2::Oid, String>,
+    /// Filters whose use was already recorded in this transaction
+    accessed: HashSet<git2::Oid>,
+    track_access: bool,
     missing: Vec<(filter::Filter, git2::Oid)>,
+    /// Commits selected by each `:squash=...` filter, see `get_squash_ids`
+    squash_ids: HashMap<git2::Oid, Vec<String>>,
     misses: usize,
     walks: usize,
 }
 
 pub struct Transaction {
     t2: std::cell::RefCell<Transaction2>,
+    _pin: filter::Pin<'static>,
+    backend: Arc<dyn CacheBackend>,
     repo: git2::Repository,
     ref_prefix: String,
 }
 
 impl Transaction {
     pub fn open(path: &std::path::Path, ref_prefix: Option<&str>) -> JoshResult<Transaction> {
-        Ok(Transaction::new(
+        Transaction::new(
             git2::Repository::open_ext(
                 path,
                 git2::RepositoryOpenFlags::NO_SEARCH,
                 &[] as &[&std::ffi::OsStr],
             )?,
             ref_prefix,
-        ))
+        )
     }
 
     pub fn status(&self, _msg: &str) {
@@ -88,50 +268,38 @@ impl Transaction {
         /* t2.out.flush().ok(); */
     }
 
-    pub fn new(repo: git2::Repository, ref_prefix: Option<&str>) -> Transaction {
+    pub fn new(repo: git2::Repository, ref_prefix: Option<&str>) -> JoshResult<Transaction> {
         log::debug!("new transaction");
-        let path_tree = DB
-            .lock()
-            .unwrap()
-            .as_ref()
-            .unwrap()
-            .open_tree("_paths")
-            .unwrap();
-        let invert_tree = DB
-            .lock()
-            .unwrap()
-            .as_ref()
-            .unwrap()
-            .open_tree("_invert")
-            .unwrap();
-        let trigram_index_tree = DB
-            .lock()
-            .unwrap()
-            .as_ref()
-            .unwrap()
-            .open_tree("_trigram_index")
-            .unwrap();
-        Transaction {
+        Ok(Transaction {
             t2: std::cell::RefCell::new(Transaction2 {
                 commit_map: HashMap::new(),
-                apply_map: HashMap::new(),
-                subtract_map: HashMap::new(),
-                unapply_map: HashMap::new(),
-                sled_trees: HashMap::new(),
-                path_tree,
-                invert_tree,
-                trigram_index_tree,
+                pairs: HashMap::new(),
+                tree_names: HashMap::new(),
+                accessed: HashSet::new(),
+                track_access: true,
                 missing: vec![],
+                squash_ids: HashMap::new(),
                 misses: 0,
                 walks: 0,
             }),
+            _pin: filter::pin(),
+            backend: backend()?,
             repo,
             ref_prefix: ref_prefix.unwrap_or("").to_string(),
-        }
+        })
     }
 
     pub fn try_clone(&self) -> JoshResult<Transaction> {
-        Transaction::open(self.repo.path(), Some(&self.ref_prefix))
+        let transaction = Transaction::open(self.repo.path(), Some(&self.ref_prefix))?;
+        transaction.t2.borrow_mut().track_access = self.t2.borrow().track_access;
+        Ok(transaction)
+    }
+
+    /// Don't record the use of filters, so background work like rebuilding known filters
+    /// does not keep them from being removed by `gc`.
+    pub fn without_access_tracking(self) -> Transaction {
+        self.t2.borrow_mut().track_access = false;
+        self
     }
 
     pub fn repo(&self) -> &git2::Repository {
@@ -156,110 +324,160 @@ impl Transaction {
         self.t2.borrow_mut().walks -= 1;
     }
 
-    pub fn insert_apply(&self, filter: filter::Filter, from: git2::Oid, to: git2::Oid) {
-        let mut t2 = self.t2.borrow_mut();
-        t2.apply_map
-            .entry(filter.id())
-            .or_insert_with(HashMap::new)
-            .insert(from, to);
+    fn get_oid(&self, tree: &str, key: &[u8]) -> JoshResult<Option<git2::Oid>> {
+        Ok(match self.backend.get(tree, key)? {
+            Some(oid) => Some(git2::Oid::from_bytes(&oid)?),
+            None => None,
+        })
     }
 
-    pub fn get_apply(&self, filter: filter::Filter, from: git2::Oid) -> Option<git2::Oid> {
-        let t2 = self.t2.borrow_mut();
-        if let Some(m) = t2.apply_map.get(&filter.id()) {
-            return m.get(&from).cloned();
+    fn insert_oid(&self, tree: &str, key: &[u8], oid: git2::Oid) -> JoshResult<()> {
+        self.backend.insert(tree, key, oid.as_bytes())
+    }
+
+    /// Look up a result keyed by two objects in `tree`, see `Transaction2::pairs`
+    fn get_pair(
+        &self,
+        tree: &'static str,
+        a: git2::Oid,
+        b: git2::Oid,
+    ) -> JoshResult<Option<git2::Oid>> {
+        if let Some(oid) = self.t2.borrow().pairs.get(&(tree, a, b)) {
+            return Ok(Some(*oid));
         }
-        None
+        let oid = some_or!(self.get_oid(tree, &pair_key(a, b))?, {
+            return Ok(None);
+        });
+        self.t2.borrow_mut().pairs.insert((tree, a, b), oid);
+        Ok(Some(oid))
     }
 
-    pub fn insert_subtract(&self, from: (git2::Oid, git2::Oid), to: git2::Oid) {
-        let mut t2 = self.t2.borrow_mut();
-        t2.subtract_map.insert(from, to);
+    fn insert_pair(
+        &self,
+        tree: &'static str,
+        a: git2::Oid,
+        b: git2::Oid,
+        result: git2::Oid,
+    ) -> JoshResult<()> {
+        self.t2.borrow_mut().pairs.insert((tree, a, b), result);
+        self.insert_oid(tree, &pair_key(a, b), result)
     }
 
-    pub fn get_subtract(&self, from: (git2::Oid, git2::Oid)) -> Option<git2::Oid> {
-        let t2 = self.t2.borrow_mut();
-        return t2.subtract_map.get(&from).cloned();
+    pub fn insert_apply(
+        &self,
+        filter: filter::Filter,
+        from: git2::Oid,
+        to: git2::Oid,
+    ) -> JoshResult<()> {
+        self.insert_pair("_apply", filter.id(), from, to)
     }
 
-    pub fn insert_unapply(&self, filter: filter::Filter, from: git2::Oid, to: git2::Oid) {
-        let mut t2 = self.t2.borrow_mut();
-        t2.unapply_map
-            .entry(filter.id())
-            .or_insert_with(HashMap::new)
-            .insert(from, to);
+    pub fn get_apply(
+        &self,
+        filter: filter::Filter,
+        from: git2::Oid,
+    ) -> JoshResult<Option<git2::Oid>> {
+        self.get_pair("_apply", filter.id(), from)
     }
 
-    pub fn insert_paths(&self, tree: (git2::Oid, String), result: git2::Oid) {
-        let t2 = self.t2.borrow();
-        let s = format!("{:?}", tree);
-        let x = git2::Oid::hash_object(git2::ObjectType::Blob, s.as_bytes()).expect("hash_object");
-        t2.path_tree
-            .insert(x.as_bytes(), result.as_bytes())
-            .unwrap();
+    pub fn insert_subtract(&self, from: (git2::Oid, git2::Oid), to: git2::Oid) -> JoshResult<()> {
+        self.insert_pair("_subtract", from.0, from.1, to)
     }
 
-    pub fn get_paths(&self, tree: (git2::Oid, String)) -> Option<git2::Oid> {
-        let t2 = self.t2.borrow();
-        let s = format!("{:?}", tree);
-        let x = git2::Oid::hash_object(git2::ObjectType::Blob, s.as_bytes()).expect("hash_object");
+    pub fn get_subtract(&self, from: (git2::Oid, git2::Oid)) -> JoshResult<Option<git2::Oid>> {
+        self.get_pair("_subtract", from.0, from.1)
+    }
 
-        if let Some(oid) = t2.path_tree.get(x.as_bytes()).unwrap() {
-            return Some(git2::Oid::from_bytes(&oid).unwrap());
-        }
-        None
+    pub fn insert_unapply(
+        &self,
+        filter: filter::Filter,
+        from: git2::Oid,
+        to: git2::Oid,
+    ) -> JoshResult<()> {
+        self.insert_pair("_unapply", filter.id(), from, to)
     }
 
-    pub fn insert_invert(&self, tree: (git2::Oid, String), result: git2::Oid) {
-        let t2 = self.t2.borrow();
-        let s = format!("{:?}", tree);
-        let x = git2::Oid::hash_object(git2::ObjectType::Blob, s.as_bytes()).expect("hash_object");
-        t2.invert_tree
-            .insert(x.as_bytes(), result.as_bytes())
-            .unwrap();
+    pub fn get_unapply(
+        &self,
+        filter: filter::Filter,
+        from: git2::Oid,
+    ) -> JoshResult<Option<git2::Oid>> {
+        self.get_pair("_unapply", filter.id(), from)
     }
 
-    pub fn get_invert(&self, tree: (git2::Oid, String)) -> Option<git2::Oid> {
-        let t2 = self.t2.borrow();
-        let s = format!("{:?}", tree);
-        let x = git2::Oid::hash_object(git2::ObjectType::Blob, s.as_bytes()).expect("hash_object");
+    pub fn insert_paths(
+        &self,
+        tree: (git2::Oid, std::path::PathBuf),
+        result: git2::Oid,
+    ) -> JoshResult<()> {
+        self.insert_oid("_paths", hashed_key(tree).as_bytes(), result)
+    }
 
-        if let Some(oid) = t2.invert_tree.get(x.as_bytes()).unwrap() {
-            return Some(git2::Oid::from_bytes(&oid).unwrap());
-        }
-        None
+    pub fn get_paths(
+        &self,
+        tree: (git2::Oid, std::path::PathBuf),
+    ) -> JoshResult<Option<git2::Oid>> {
+        self.get_oid("_paths", hashed_key(tree).as_bytes())
     }
 
-    pub fn insert_trigram_index(&self, tree: git2::Oid, result: git2::Oid) {
-        let t2 = self.t2.borrow();
-        t2.trigram_index_tree
-            .insert(tree.as_bytes(), result.as_bytes())
-            .unwrap();
+    pub fn insert_invert(
+        &self,
+        tree: (git2::Oid, std::path::PathBuf),
+        result: git2::Oid,
+    ) -> JoshResult<()> {
+        self.insert_oid("_invert", hashed_key(tree).as_bytes(), result)
     }
 
-    pub fn get_trigram_index(&self, tree: git2::Oid) -> Option<git2::Oid> {
-        let t2 = self.t2.borrow();
+    pub fn get_invert(
+        &self,
+        tree: (git2::Oid, std::path::PathBuf),
+    ) -> JoshResult<Option<git2::Oid>> {
+        self.get_oid("_invert", hashed_key(tree).as_bytes())
+    }
+
+    pub fn insert_plugin(
+        &self,
+        plugin: (String, String, git2::Oid),
+        result: git2::Oid,
+    ) -> JoshResult<()> {
+        self.insert_oid("_plugin", hashed_key(plugin).as_bytes(), result)
+    }
 
-        if let Some(oid) = t2.trigram_index_tree.get(tree.as_bytes()).unwrap() {
-            return Some(git2::Oid::from_bytes(&oid).unwrap());
+    pub fn get_plugin(&self, plugin: (String, String, git2::Oid)) -> JoshResult<Option<git2::Oid>> {
+        if let Some(oid) = self.get_oid("_plugin", hashed_key(plugin).as_bytes())? {
+            if self.repo.odb()?.exists(oid) {
+                return Ok(Some(oid));
+            }
         }
-        None
+        Ok(None)
+    }
+
+    pub fn insert_trigram_index(&self, tree: git2::Oid, result: git2::Oid) -> JoshResult<()> {
+        self.insert_oid("_trigram_index", tree.as_bytes(), result)
+    }
+
+    pub fn get_trigram_index(&self, tree: git2::Oid) -> JoshResult<Option<git2::Oid>> {
+        self.get_oid("_trigram_index", tree.as_bytes())
     }
 
-    pub fn insert_populate(&self, tree: (git2::Oid, git2::Oid), result: git2::Oid) {
-        POPULATE_MAP.lock().unwrap().entry(tree).or_insert(result);
+    pub fn insert_populate(
+        &self,
+        tree: (git2::Oid, git2::Oid),
+        result: git2::Oid,
+    ) -> JoshResult<()> {
+        self.insert_pair("_populate", tree.0, tree.1, result)
     }
 
-    pub fn get_populate(&self, tree: (git2::Oid, git2::Oid)) -> Option<git2::Oid> {
-        return POPULATE_MAP.lock().unwrap().get(&tree).cloned();
+    pub fn get_populate(&self, tree: (git2::Oid, git2::Oid)) -> JoshResult<Option<git2::Oid>> {
+        self.get_pair("_populate", tree.0, tree.1)
     }
 
-    pub fn insert_glob(&self, tree: (git2::Oid, git2::Oid), result: git2::Oid) {
-        GLOB_MAP.lock().unwrap().entry(tree).or_insert(result);
+    pub fn insert_glob(&self, tree: (git2::Oid, git2::Oid), result: git2::Oid) -> JoshResult<()> {
+        self.insert_pair("_glob", tree.0, tree.1, result)
     }
 
-    pub fn get_glob(&self, tree: (git2::Oid, git2::Oid)) -> Option<git2::Oid> {
-        return GLOB_MAP.lock().unwrap().get(&tree).cloned();
+    pub fn get_glob(&self, tree: (git2::Oid, git2::Oid)) -> JoshResult<Option<git2::Oid>> {
+        self.get_pair("_glob", tree.0, tree.1)
     }
 
     pub fn insert_ref(&self, filter: filter::Filter, from: git2::Oid, to: git2::Oid) {
@@ -282,17 +500,42 @@ impl Transaction {
         None
     }
 
-    pub fn get_unapply(&self, filter: filter::Filter, from: git2::Oid) -> Option<git2::Oid> {
-        let t2 = self.t2.borrow_mut();
-        if let Some(m) = t2.unapply_map.get(&filter.id()) {
-            return m.get(&from).cloned();
+    /// Name of the tree storing the commits of `filter`
+    fn tree_name(&self, filter: filter::Filter) -> String {
+        self.t2
+            .borrow_mut()
+            .tree_names
+            .entry(filter.id())
+            .or_insert_with(|| filter::spec(filter))
+            .clone()
+    }
+
+    /// Record that `filter` was used, once per transaction. The uses are written to the
+    /// backend together by `flush`.
+    fn touch(&self, filter: filter::Filter) {
+        {
+            let mut t2 = self.t2.borrow_mut();
+            if !t2.track_access || !t2.accessed.insert(filter.id()) {
+                return;
+            }
+        }
+        let name = self.tree_name(filter);
+        if let Ok(mut accessed) = ACCESSED.lock() {
+            accessed.insert(name, now());
         }
-        None
     }
 
-    pub fn insert(&self, filter: filter::Filter, from: git2::Oid, to: git2::Oid, store: bool) {
-        let mut t2 = self.t2.borrow_mut();
-        t2.commit_map
+    pub fn insert(
+        &self,
+        filter: filter::Filter,
+        from: git2::Oid,
+        to: git2::Oid,
+        store: bool,
+    ) -> JoshResult<()> {
+        self.touch(filter);
+        self.t2
+            .borrow_mut()
+            .commit_map
             .entry(filter.id())
             .or_insert_with(HashMap::new)
             .insert(from, to);
@@ -301,86 +544,301 @@ impl Transaction {
         // random extra commits (probability 1/256) to avoid long searches for filters that reduce
         // the history length by a very large factor.
         if store || from.as_bytes()[0] == 0 {
-            let t = t2.sled_trees.entry(filter.id()).or_insert_with(|| {
-                DB.lock()
-                    .unwrap()
-                    .as_ref()
-                    .unwrap()
-                    .open_tree(filter::spec(filter))
-                    .unwrap()
-            });
-
-            t.insert(from.as_bytes(), to.as_bytes()).unwrap();
+            let name = self.tree_name(filter);
+            self.insert_oid(&name, from.as_bytes(), to)?;
+            with_stats(|stats| stats.record(&name, filter, |x| x.results += 1))?;
         }
+        Ok(())
+    }
+
+    /// Approximate statistics of `filter`, see `update_stats`
+    pub fn stats(&self, filter: filter::Filter) -> JoshResult<FilterStats> {
+        let name = self.tree_name(filter);
+        with_stats(|stats| stats.trees.get(&name).copied().unwrap_or_default())
     }
-    pub fn len(&self, filter: filter::Filter) -> usize {
-        let mut t2 = self.t2.borrow_mut();
-        let t = t2.sled_trees.entry(filter.id()).or_insert_with(|| {
-            DB.lock()
-                .unwrap()
-                .as_ref()
-                .unwrap()
-                .open_tree(filter::spec(filter))
-                .unwrap()
-        });
 
-        t.len()
+    /// Filters that have results stored in the cache, in the order of their names, so the
+    /// order does not depend on the host
+    pub fn cached_filters(&self) -> JoshResult<Vec<filter::Filter>> {
+        with_stats(|stats| {
+            let mut cached = vec![];
+            for (name, s) in stats.trees.iter() {
+                if s.results == 0 || name.contains("SUBTRACT") {
+                    continue;
+                }
+                // Parsed filters are kept, unless they were removed meanwhile by `collect`
+                let filter = match stats.filters.get(name) {
+                    Some(filter) if filter::is_known(*filter) => *filter,
+                    _ => ok_or!(filter::parse(name), {
+                        continue;
+                    }),
+                };
+                stats.filters.insert(name.clone(), filter);
+                cached.push(filter);
+            }
+            cached
+        })
     }
 
-    pub fn get_missing(&self) -> Vec<(filter::Filter, git2::Oid)> {
+    /// Commit ids the selectors of the `:squash=...` filter `filter` resolved to. Ref
+    /// patterns are only resolved once per transaction, not for every filtered commit.
+    pub fn get_squash_ids(&self, filter: filter::Filter) -> Option<Vec<String>> {
+        self.t2.borrow().squash_ids.get(&filter.id()).cloned()
+    }
+
+    pub fn insert_squash_ids(&self, filter: filter::Filter, ids: Vec<String>) {
+        self.t2.borrow_mut().squash_ids.insert(filter.id(), ids);
+    }
+
+    pub fn get_missing(&self) -> JoshResult<Vec<(filter::Filter, git2::Oid)>> {
         let mut missing = self.t2.borrow().missing.clone();
         missing.sort();
         missing.dedup();
-        missing.retain(|(f, i)| !self.known(*f, *i));
-        self.t2.borrow_mut().missing = missing.clone();
-        missing
+        let mut unknown = vec![];
+        for (f, i) in missing {
+            if !self.known(f, i)? {
+                unknown.push((f, i));
+            }
+        }
+        self.t2.borrow_mut().missing = unknown.clone();
+        Ok(unknown)
     }
 
-    pub fn known(&self, filter: filter::Filter, from: git2::Oid) -> bool {
-        self.get2(filter, from).is_some()
+    pub fn known(&self, filter: filter::Filter, from: git2::Oid) -> JoshResult<bool> {
+        Ok(self.get2(filter, from)?.is_some())
     }
 
-    pub fn get(&self, filter: filter::Filter, from: git2::Oid) -> Option<git2::Oid> {
-        if let Some(x) = self.get2(filter, from) {
-            Some(x)
+    pub fn get(&self, filter: filter::Filter, from: git2::Oid) -> JoshResult<Option<git2::Oid>> {
+        if let Some(x) = self.get2(filter, from)? {
+            Ok(Some(x))
         } else {
-            let mut t2 = self.t2.borrow_mut();
-            t2.misses += 1;
-            t2.missing.push((filter, from));
-            None
+            {
+                let mut t2 = self.t2.borrow_mut();
+                t2.misses += 1;
+                t2.missing.push((filter, from));
+            }
+            let name = self.tree_name(filter);
+            with_stats(|stats| stats.record(&name, filter, |x| x.misses += 1))?;
+            Ok(None)
         }
     }
 
-    fn get2(&self, filter: filter::Filter, from: git2::Oid) -> Option<git2::Oid> {
+    /// The results of `filter` for all of `ids`, or `None` if one of them is not known yet
+    pub fn get_all(
+        &self,
+        filter: filter::Filter,
+        ids: impl IntoIterator<Item = git2::Oid>,
+    ) -> JoshResult<Option<Vec<git2::Oid>>> {
+        let mut result = vec![];
+        for id in ids {
+            result.push(some_or!(self.get(filter, id)?, { return Ok(None) }));
+        }
+        Ok(Some(result))
+    }
+
+    fn get2(&self, filter: filter::Filter, from: git2::Oid) -> JoshResult<Option<git2::Oid>> {
         if filter == filter::nop() {
-            return Some(from);
+            return Ok(Some(from));
         }
-        let mut t2 = self.t2.borrow_mut();
-        if let Some(m) = t2.commit_map.get(&filter.id()) {
+        self.touch(filter);
+        if let Some(m) = self.t2.borrow().commit_map.get(&filter.id()) {
             if let Some(oid) = m.get(&from).cloned() {
-                return Some(oid);
+                return Ok(Some(oid));
             }
         }
-        let t = t2.sled_trees.entry(filter.id()).or_insert_with(|| {
-            DB.lock()
-                .unwrap()
-                .as_ref()
-                .unwrap()
-                .open_tree(filter::spec(filter))
-                .unwrap()
-        });
-        if let Some(oid) = t.get(from.as_bytes()).unwrap() {
-            let oid = git2::Oid::from_bytes(&oid).unwrap();
+        if let Some(oid) = self.get_oid(&self.tree_name(filter), from.as_bytes())? {
             if oid == git2::Oid::zero() {
-                return Some(oid);
+                return Ok(Some(oid));
             }
-            if self.repo.odb().unwrap().exists(oid) {
+            if self.repo.odb()?.exists(oid) {
                 // Only report an object as cached if it exists in the object database.
                 // This forces a rebuild in case the object was garbage collected.
-                return Some(oid);
+                return Ok(Some(oid));
             }
         }
 
-        None
+        Ok(None)
     }
 }
+
+/// Key of results that depend on two objects
+fn pair_key(a: git2::Oid, b: git2::Oid) -> Vec<u8> {
+    [a.as_bytes(), b.as_bytes()].concat()
+}
+
+fn hashed_key(key: impl std::fmt::Debug) -> git2::Oid {
+    let s = format!("{:?}", key);
+    git2::Oid::hash_object(git2::ObjectType::Blob, s.as_bytes()).expect("hash_object")
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Default, Debug)]
+pub struct GcStats {
+    /// Filters all results of which were removed
+    pub filters: usize,
+    pub entries: usize,
+    /// Total size of the keys and values of the removed entries, not including any
+    /// overhead of the backend
+    pub bytes: usize,
+}
+
+impl GcStats {
+    fn remove(
+        &mut self,
+        backend: &dyn CacheBackend,
+        tree: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> JoshResult<()> {
+        backend.remove(tree, key)?;
+        self.entries += 1;
+        self.bytes += key.len() + value.len();
+        Ok(())
+    }
+}
+
+fn oid(bytes: &[u8]) -> Option<git2::Oid> {
+    git2::Oid::from_bytes(bytes).ok()
+}
+
+/// Add all commits reachable from `start` to `reachable`
+fn mark_reachable(
+    repo: &git2::Repository,
+    start: Vec<git2::Oid>,
+    reachable: &mut HashSet<git2::Oid>,
+) {
+    let mut todo = start;
+    while let Some(id) = todo.pop() {
+        if !reachable.insert(id) {
+            continue;
+        }
+        if let Ok(commit) = repo.find_commit(id) {
+            todo.extend(commit.parent_ids());
+        }
+    }
+}
+
+/// Remove entries from the cache that are unlikely to be used again:
+///  * All results of filters that have not been used for longer than `max_age`.
+///  * Results for commits that are not reachable from any reference or `HEAD`, for example
+///    after a force push. Commits that are the result of filtering a reachable commit
+///    count as reachable, as chained filters are applied to them. Nothing is removed for
+///    this reason if the repository has no references at all.
+///  * Results that refer to objects no longer present in the object database.
+pub fn gc(transaction: &Transaction, max_age: std::time::Duration) -> JoshResult<GcStats> {
+    use std::convert::TryInto;
+
+    let backend = backend()?;
+    let repo = transaction.repo();
+    let now = now();
+    let mut stats = GcStats::default();
+
+    write_access(&*backend)?;
+    let mut access: HashMap<String, u64> = HashMap::new();
+    for (name, time) in backend.entries(ACCESS)? {
+        let time = time.as_slice().try_into().map(u64::from_le_bytes);
+        access.insert(String::from_utf8(name)?, time.unwrap_or(0));
+    }
+
+    let trees = backend.trees()?;
+    let mut abandoned = HashSet::new();
+    let mut commit_trees = vec![];
+    for name in trees.iter().filter(|name| !name.starts_with('_')) {
+        match access.get(name) {
+            // Results stored before the use of filters was recorded
+            None => backend.insert(ACCESS, name.as_bytes(), &now.to_le_bytes())?,
+            Some(time) if now.saturating_sub(*time) > max_age.as_secs() => {
+                for (key, value) in backend.entries(name)? {
+                    stats.entries += 1;
+                    stats.bytes += key.len() + value.len();
+                }
+                backend.clear(name)?;
+                stats.filters += 1;
+                if let Ok(filter) = filter::parse(name) {
+                    abandoned.insert(filter.id());
+                }
+                continue;
+            }
+            Some(_) => {}
+        }
+        commit_trees.push(name.as_str());
+    }
+
+    for (name, time) in access.iter() {
+        let used = commit_trees.iter().any(|tree| tree == name);
+        if !used && now.saturating_sub(*time) > max_age.as_secs() {
+            backend.remove(ACCESS, name.as_bytes())?;
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    let mut start = vec![];
+    if let Ok(commit) = repo.head().and_then(|head| head.peel_to_commit()) {
+        start.push(commit.id());
+    }
+    for reference in repo.references()? {
+        if let Ok(commit) = reference?.peel_to_commit() {
+            start.push(commit.id());
+        }
+    }
+    let prune_unreachable = !start.is_empty();
+    // Filtered commits can in turn be the input of other filters, so results are reachable
+    // until no new commits are found. Trees are read one at a time to bound the memory use.
+    while !start.is_empty() {
+        mark_reachable(repo, start, &mut reachable);
+        start = vec![];
+        for name in commit_trees.iter() {
+            for (key, value) in backend.entries(name)? {
+                if !oid(&key).map(|x| reachable.contains(&x)).unwrap_or(false) {
+                    continue;
+                }
+                if let Some(to) = oid(&value) {
+                    if !to.is_zero() && !reachable.contains(&to) {
+                        start.push(to);
+                    }
+                }
+            }
+        }
+    }
+
+    for name in commit_trees.iter().filter(|_| prune_unreachable) {
+        for (key, value) in backend.entries(name)? {
+            if let Some(from) = oid(&key) {
+                if !reachable.contains(&from) {
+                    stats.remove(&*backend, name, &key, &value)?;
+                }
+            }
+        }
+    }
+
+    let odb = repo.odb()?;
+    for name in trees
+        .iter()
+        .filter(|name| name.starts_with('_') && *name != ACCESS && *name != STATS)
+    {
+        let keyed_by_filter = name == "_apply" || name == "_unapply";
+        for (key, value) in backend.entries(name)? {
+            let filter_abandoned = keyed_by_filter
+                && key.len() > 20
+                && oid(&key[..20])
+                    .map(|x| abandoned.contains(&x))
+                    .unwrap_or(false);
+            let missing = oid(&value)
+                .map(|x| !x.is_zero() && !odb.exists(x))
+                .unwrap_or(false);
+            if filter_abandoned || missing {
+                stats.remove(&*backend, name, &key, &value)?;
+            }
+        }
+    }
+
+    update_stats()?;
+    backend.flush()?;
+    Ok(stats)
+}
@@ -0,0 +1,385 @@
+use super::*;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Storage of the cache. Data is organized in named trees, each mapping byte string keys
+/// to byte string values.
+pub trait CacheBackend: Send + Sync {
+    fn get(&self, tree: &str, key: &[u8]) -> JoshResult<Option<Vec<u8>>>;
+    fn insert(&self, tree: &str, key: &[u8], value: &[u8]) -> JoshResult<()>;
+    fn remove(&self, tree: &str, key: &[u8]) -> JoshResult<()>;
+
+    /// All entries of `tree`
+    fn entries(&self, tree: &str) -> JoshResult<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    /// Remove all entries of `tree`
+    fn clear(&self, tree: &str) -> JoshResult<()>;
+
+    /// Number of entries in `tree`
+    fn len(&self, tree: &str) -> JoshResult<usize>;
+
+    /// Names of all trees that contain entries
+    fn trees(&self) -> JoshResult<Vec<String>>;
+
+    /// Make sure everything inserted so far is persisted
+    fn flush(&self) -> JoshResult<()>;
+}
+
+type MemoryTree = HashMap<Vec<u8>, Vec<u8>>;
+
+/// Keeps everything in memory, nothing is persisted. Useful for tests and one-off runs.
+#[derive(Default)]
+pub struct MemoryBackend {
+    trees: RwLock<HashMap<String, MemoryTree>>,
+}
+
+impl CacheBackend for MemoryBackend {
+    fn get(&self, tree: &str, key: &[u8]) -> JoshResult<Option<Vec<u8>>> {
+        Ok(self
+            .trees
+            .read()?
+            .get(tree)
+            .and_then(|t| t.get(key))
+            .cloned())
+    }
+
+    fn insert(&self, tree: &str, key: &[u8], value: &[u8]) -> JoshResult<()> {
+        self.trees
+            .write()?
+            .entry(tree.to_string())
+            .or_default()
+            .insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, tree: &str, key: &[u8]) -> JoshResult<()> {
+        if let Some(t) = self.trees.write()?.get_mut(tree) {
+            t.remove(key);
+        }
+        Ok(())
+    }
+
+    fn entries(&self, tree: &str) -> JoshResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .trees
+            .read()?
+            .get(tree)
+            .map(|t| t.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default())
+    }
+
+    fn clear(&self, tree: &str) -> JoshResult<()> {
+        self.trees.write()?.remove(tree);
+        Ok(())
+    }
+
+    fn len(&self, tree: &str) -> JoshResult<usize> {
+        Ok(self.trees.read()?.get(tree).map(|t| t.len()).unwrap_or(0))
+    }
+
+    fn trees(&self) -> JoshResult<Vec<String>> {
+        Ok(self.trees.read()?.keys().cloned().collect())
+    }
+
+    fn flush(&self) -> JoshResult<()> {
+        Ok(())
+    }
+}
+
+/// Stores every tree of the cache in a tree of a sled database
+pub struct SledBackend {
+    db: sled::Db,
+    trees: RwLock<HashMap<String, sled::Tree>>,
+}
+
+impl SledBackend {
+    pub fn open(path: &std::path::Path) -> JoshResult<SledBackend> {
+        Ok(SledBackend {
+            db: sled::Config::default()
+                .path(path)
+                .flush_every_ms(Some(200))
+                .open()?,
+            trees: RwLock::new(HashMap::new()),
+        })
+    }
+
+    fn tree(&self, name: &str) -> JoshResult<sled::Tree> {
+        if let Some(tree) = self.trees.read()?.get(name) {
+            return Ok(tree.clone());
+        }
+        let tree = self.db.open_tree(name)?;
+        self.trees.write()?.insert(name.to_string(), tree.clone());
+        Ok(tree)
+    }
+}
+
+impl CacheBackend for SledBackend {
+    fn get(&self, tree: &str, key: &[u8]) -> JoshResult<Option<Vec<u8>>> {
+        Ok(self.tree(tree)?.get(key)?.map(|x| x.to_vec()))
+    }
+
+    fn insert(&self, tree: &str, key: &[u8], value: &[u8]) -> JoshResult<()> {
+        self.tree(tree)?.insert(key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, tree: &str, key: &[u8]) -> JoshResult<()> {
+        self.tree(tree)?.remove(key)?;
+        Ok(())
+    }
+
+    fn entries(&self, tree: &str) -> JoshResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut entries = vec![];
+        for entry in self.tree(tree)?.iter() {
+            let (k, v) = entry?;
+            entries.push((k.to_vec(), v.to_vec()));
+        }
+        Ok(entries)
+    }
+
+    fn clear(&self, tree: &str) -> JoshResult<()> {
+        self.trees.write()?.remove(tree);
+        self.db.drop_tree(tree)?;
+        Ok(())
+    }
+
+    fn len(&self, tree: &str) -> JoshResult<usize> {
+        Ok(self.tree(tree)?.len())
+    }
+
+    fn trees(&self) -> JoshResult<Vec<String>> {
+        let mut trees = vec![];
+        for name in self.db.tree_names() {
+            let name = String::from_utf8(name.to_vec())?;
+            if name != "__sled__default" && !self.db.open_tree(&name)?.is_empty() {
+                trees.push(name);
+            }
+        }
+        Ok(trees)
+    }
+
+    fn flush(&self) -> JoshResult<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+type SqliteBatch = HashMap<(String, Vec<u8>), Option<Vec<u8>>>;
+
+/// Stores the whole cache in a single SQLite file, with one row per entry in the table
+/// `entries(tree, key, value)`. Writes are buffered and committed in batches by a background
+/// thread. Reads use a pool of connections, so they neither wait for each other nor for writes.
+pub struct SqliteBackend {
+    shared: Arc<SqliteShared>,
+}
+
+struct SqliteShared {
+    path: std::path::PathBuf,
+    writer: Mutex<rusqlite::Connection>,
+    readers: Mutex<Vec<rusqlite::Connection>>,
+    buffer: Mutex<SqliteBuffer>,
+}
+
+/// Writes that are not visible to readers yet, `None` marking a removed entry
+#[derive(Default)]
+struct SqliteBuffer {
+    pending: SqliteBatch,
+    /// The batch currently being committed
+    committing: Arc<SqliteBatch>,
+}
+
+const SQLITE_COMMIT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+impl SqliteBackend {
+    pub fn open(path: &std::path::Path) -> JoshResult<SqliteBackend> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let writer = rusqlite::Connection::open(path)?;
+        writer.busy_timeout(std::time::Duration::from_secs(10))?;
+        writer.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS entries (
+                 tree TEXT NOT NULL,
+                 key BLOB NOT NULL,
+                 value BLOB NOT NULL,
+                 PRIMARY KEY (tree, key)
+             ) WITHOUT ROWID;",
+        )?;
+        let shared = Arc::new(SqliteShared {
+            path: path.to_owned(),
+            writer: Mutex::new(writer),
+            readers: Mutex::new(vec![]),
+            buffer: Default::default(),
+        });
+
+        // Commit on a timer, so writes become visible to other processes and survive crashes
+        // even when nothing else is written.
+        let weak = Arc::downgrade(&shared);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(SQLITE_COMMIT_INTERVAL);
+            match weak.upgrade() {
+                Some(shared) => {
+                    if let Err(JoshError(e)) = shared.commit() {
+                        log::error!("committing cache failed: {}", e);
+                    }
+                }
+                None => break,
+            }
+        });
+
+        Ok(SqliteBackend { shared })
+    }
+}
+
+impl SqliteShared {
+    fn commit(&self) -> JoshResult<()> {
+        let mut writer = self.writer.lock()?;
+        let batch = {
+            let mut buffer = self.buffer.lock()?;
+            if buffer.pending.is_empty() {
+                return Ok(());
+            }
+            buffer.committing = Arc::new(std::mem::take(&mut buffer.pending));
+            buffer.committing.clone()
+        };
+
+        let transaction = writer.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO entries (tree, key, value) VALUES (?1, ?2, ?3)",
+            )?;
+            let mut delete =
+                transaction.prepare_cached("DELETE FROM entries WHERE tree = ?1 AND key = ?2")?;
+            for ((tree, key), value) in batch.iter() {
+                match value {
+                    Some(value) => insert.execute(rusqlite::params![tree, key, value])?,
+                    None => delete.execute(rusqlite::params![tree, key])?,
+                };
+            }
+        }
+        transaction.commit()?;
+
+        self.buffer.lock()?.committing = Default::default();
+        Ok(())
+    }
+
+    fn buffered(&self, tree: &str, key: &[u8]) -> JoshResult<Option<Option<Vec<u8>>>> {
+        let buffer = self.buffer.lock()?;
+        let key = (tree.to_string(), key.to_vec());
+        Ok(buffer
+            .pending
+            .get(&key)
+            .or_else(|| buffer.committing.get(&key))
+            .cloned())
+    }
+
+    fn write(&self, tree: &str, key: &[u8], value: Option<&[u8]>) -> JoshResult<()> {
+        self.buffer
+            .lock()?
+            .pending
+            .insert((tree.to_string(), key.to_vec()), value.map(|x| x.to_vec()));
+        Ok(())
+    }
+
+    /// Run `f` with a connection from the pool of readers
+    fn read<T>(
+        &self,
+        f: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T>,
+    ) -> JoshResult<T> {
+        let connection = self.readers.lock()?.pop();
+        let connection = match connection {
+            Some(connection) => connection,
+            None => {
+                let connection = rusqlite::Connection::open(&self.path)?;
+                connection.busy_timeout(std::time::Duration::from_secs(10))?;
+                connection
+            }
+        };
+        let result = f(&connection);
+        self.readers.lock()?.push(connection);
+        Ok(result?)
+    }
+}
+
+impl CacheBackend for SqliteBackend {
+    fn get(&self, tree: &str, key: &[u8]) -> JoshResult<Option<Vec<u8>>> {
+        if let Some(value) = self.shared.buffered(tree, key)? {
+            return Ok(value);
+        }
+        self.shared.read(|c| {
+            let mut statement =
+                c.prepare_cached("SELECT value FROM entries WHERE tree = ?1 AND key = ?2")?;
+            let mut rows = statement.query(rusqlite::params![tree, key])?;
+            match rows.next()? {
+                Some(row) => Ok(Some(row.get(0)?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn insert(&self, tree: &str, key: &[u8], value: &[u8]) -> JoshResult<()> {
+        self.shared.write(tree, key, Some(value))
+    }
+
+    fn remove(&self, tree: &str, key: &[u8]) -> JoshResult<()> {
+        self.shared.write(tree, key, None)
+    }
+
+    // The following commit first, so the database is up to date
+
+    fn entries(&self, tree: &str) -> JoshResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.shared.commit()?;
+        self.shared.read(|c| {
+            let mut statement =
+                c.prepare_cached("SELECT key, value FROM entries WHERE tree = ?1")?;
+            let entries = statement
+                .query_map(rusqlite::params![tree], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    fn clear(&self, tree: &str) -> JoshResult<()> {
+        self.shared.commit()?;
+        self.shared.writer.lock()?.execute(
+            "DELETE FROM entries WHERE tree = ?1",
+            rusqlite::params![tree],
+        )?;
+        Ok(())
+    }
+
+    fn len(&self, tree: &str) -> JoshResult<usize> {
+        self.shared.commit()?;
+        let n: i64 = self.shared.read(|c| {
+            c.query_row(
+                "SELECT count(*) FROM entries WHERE tree = ?1",
+                rusqlite::params![tree],
+                |row| row.get(0),
+            )
+        })?;
+        Ok(n as usize)
+    }
+
+    fn trees(&self) -> JoshResult<Vec<String>> {
+        self.shared.commit()?;
+        self.shared.read(|c| {
+            let mut statement = c.prepare_cached("SELECT DISTINCT tree FROM entries")?;
+            let trees = statement
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(trees)
+        })
+    }
+
+    fn flush(&self) -> JoshResult<()> {
+        self.shared.commit()
+    }
+}
+
+impl Drop for SqliteBackend {
+    fn drop(&mut self) {
+        self.shared.commit().ok();
+    }
+}
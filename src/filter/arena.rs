@@ -0,0 +1,579 @@
+/*
+ * Interning of filters.
+ *
+ * Every `Op` is stored once in a global arena and referred to by its `Filter` id, which is
+ * the hash of a canonical binary encoding of the op. Nested filters are encoded by their id,
+ * so interning is linear in the size of the op and not of the whole filter tree.
+ *
+ * The arena is split into shards, each of which is an immutable map that is replaced as a
+ * whole when nodes are added or removed. Lookups therefore never wait, and old maps are freed
+ * once no thread can still be reading them, using epoch based reclamation. Lookups are also
+ * served from a per thread cache.
+ *
+ * Nodes are reclaimed by `collect`, see there. Code holding on to filters must keep a `Pin`
+ * while doing so.
+ */
+
+use super::*;
+use crossbeam_epoch::{Atomic, Guard, Owned};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+const SHARDS: usize = 64;
+
+/// Number of epochs in which pins are counted separately, see `Arena::pin`
+const PIN_SLOTS: usize = 64;
+
+/// Results of filter transformations that are remembered for each node
+#[derive(Clone, Copy)]
+pub(super) enum Memo {
+    Optimized,
+    Simplified,
+    /// Result of a single optimization step
+    Step,
+    Unref,
+}
+
+struct Node {
+    op: Arc<Op>,
+    children: Vec<Filter>,
+    /// Epoch in which the node was last looked up from the arena
+    used: AtomicU64,
+    memos: [once_cell::sync::OnceCell<Filter>; 4],
+}
+
+type Map = HashMap<Filter, Arc<Node>>;
+
+struct Arena {
+    shards: Vec<Atomic<Map>>,
+    epoch: AtomicU64,
+    /// Number of live pins by the epoch in which they were taken, modulo `PIN_SLOTS`
+    pins: Vec<AtomicUsize>,
+    /// Filters found unused by the previous `collect`, also keeps calls of it from overlapping
+    unused: Mutex<HashSet<Filter>>,
+}
+
+impl Default for Arena {
+    fn default() -> Arena {
+        Arena {
+            shards: (0..SHARDS).map(|_| Atomic::new(Map::new())).collect(),
+            epoch: AtomicU64::new(1),
+            pins: (0..PIN_SLOTS).map(|_| AtomicUsize::new(0)).collect(),
+            unused: Default::default(),
+        }
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        for shard in self.shards.iter() {
+            // Nothing else can refer to the maps anymore
+            unsafe {
+                drop(
+                    shard
+                        .load(Ordering::Relaxed, crossbeam_epoch::unprotected())
+                        .into_owned(),
+                )
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref ARENA: Arena = Arena::default();
+}
+
+thread_local! {
+    static LOCAL: std::cell::RefCell<(u64, HashMap<Filter, Arc<Node>>)> =
+        std::cell::RefCell::new((0, HashMap::new()));
+}
+
+/// Keeps all filters used after it was taken from being removed by `collect`
+pub struct Pin<'a> {
+    arena: &'a Arena,
+    epoch: u64,
+}
+
+impl Drop for Pin<'_> {
+    fn drop(&mut self) {
+        self.arena.pins[slot(self.epoch)].fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn slot(epoch: u64) -> usize {
+    (epoch % PIN_SLOTS as u64) as usize
+}
+
+fn load<'g>(shard: &Atomic<Map>, guard: &'g Guard) -> &'g Map {
+    // Maps are never null, and only freed once all guards that could have loaded them are gone
+    unsafe { shard.load(Ordering::SeqCst, guard).deref() }
+}
+
+impl Arena {
+    fn shard(&self, filter: Filter) -> &Atomic<Map> {
+        &self.shards[filter.id().as_bytes()[0] as usize % SHARDS]
+    }
+
+    fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    fn pin(&self) -> Pin<'_> {
+        loop {
+            let epoch = self.epoch();
+            let count = &self.pins[slot(epoch)];
+            count.fetch_add(1, Ordering::SeqCst);
+            // Otherwise a concurrent `collect` might not have seen the pin
+            if self.epoch() == epoch {
+                return Pin { arena: self, epoch };
+            }
+            count.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Record that `node` is used. If the epoch did not change meanwhile, the next `collect`
+    /// is guaranteed to see the use.
+    fn touch(&self, node: &Node) {
+        loop {
+            let epoch = self.epoch();
+            if node.used.load(Ordering::SeqCst) < epoch {
+                node.used.fetch_max(epoch, Ordering::SeqCst);
+            }
+            if self.epoch() == epoch {
+                return;
+            }
+        }
+    }
+
+    fn get(&self, filter: Filter) -> Option<Arc<Node>> {
+        let guard = crossbeam_epoch::pin();
+        load(self.shard(filter), &guard).get(&filter).cloned()
+    }
+
+    fn node(&self, filter: Filter) -> Option<Arc<Node>> {
+        let node = self.get(filter)?;
+        self.touch(&node);
+        Some(node)
+    }
+
+    /// Replace the map of `shard` by `f` applied to it, unless that returns `None`
+    fn update(&self, shard: &Atomic<Map>, f: impl Fn(&Map) -> Option<Map>) {
+        let guard = crossbeam_epoch::pin();
+        let mut current = shard.load(Ordering::SeqCst, &guard);
+        loop {
+            // As in `load`
+            let map = unsafe { current.deref() };
+            let new = some_or!(f(map), {
+                return;
+            });
+            match shard.compare_exchange(
+                current,
+                Owned::new(new),
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+                &guard,
+            ) {
+                Ok(_) => {
+                    unsafe { guard.defer_destroy(current) };
+                    return;
+                }
+                Err(e) => current = e.current,
+            }
+        }
+    }
+
+    fn intern(&self, op: Op) -> Filter {
+        let mut encoder = Encoder::default();
+        encoder.op(&op);
+        let filter = Filter(
+            git2::Oid::hash_object(git2::ObjectType::Blob, &encoder.bytes)
+                .expect("hash_object filter"),
+        );
+
+        let node = self.get(filter).unwrap_or_else(|| {
+            Arc::new(Node {
+                op: Arc::new(op),
+                children: encoder.children,
+                used: AtomicU64::new(0),
+                memos: Default::default(),
+            })
+        });
+        self.touch(&node);
+        // An existing node may have been removed by a concurrent `collect` before it was touched
+        self.update(self.shard(filter), |map| {
+            if map.contains_key(&filter) {
+                return None;
+            }
+            let mut map = map.clone();
+            map.insert(filter, node.clone());
+            Some(map)
+        });
+        filter
+    }
+
+    fn collect(&self) -> usize {
+        let mut unused = self.unused.lock().unwrap();
+        let previous = self.epoch();
+        // The pins of the next epoch are counted in the same slot as those of this old one
+        if self.pins[slot(previous + 1)].load(Ordering::SeqCst) != 0 {
+            return 0;
+        }
+        self.epoch.store(previous + 1, Ordering::SeqCst);
+        let oldest_pin = ((previous + 2).saturating_sub(PIN_SLOTS as u64)..=previous)
+            .find(|epoch| self.pins[slot(*epoch)].load(Ordering::SeqCst) != 0);
+        let threshold = oldest_pin.unwrap_or(previous);
+
+        let guard = crossbeam_epoch::pin();
+        let maps: Vec<&Map> = self.shards.iter().map(|x| load(x, &guard)).collect();
+        let index = |filter: Filter| filter.id().as_bytes()[0] as usize % SHARDS;
+
+        let mut live = HashSet::new();
+        let mut todo: Vec<Filter> = maps
+            .iter()
+            .flat_map(|map| map.iter())
+            .filter(|(_, node)| node.used.load(Ordering::SeqCst) >= threshold)
+            .map(|(filter, _)| *filter)
+            .collect();
+
+        while let Some(filter) = todo.pop() {
+            if !live.insert(filter) {
+                continue;
+            }
+            if let Some(node) = maps[index(filter)].get(&filter) {
+                todo.extend(node.children.iter().copied());
+                todo.extend(node.memos.iter().filter_map(|x| x.get().copied()));
+            }
+        }
+
+        // Uses that started before the epoch changed may be missed, but not twice in a row.
+        // Nodes that are used nevertheless are kept, these are only new or interned again.
+        let found: HashSet<Filter> = maps
+            .iter()
+            .flat_map(|map| map.keys())
+            .filter(|filter| !live.contains(*filter))
+            .copied()
+            .collect();
+        let remove: HashSet<Filter> = found.intersection(&unused).copied().collect();
+        *unused = found.difference(&remove).copied().collect();
+
+        let mut removed = 0;
+        for shard in self.shards.iter() {
+            let count = std::cell::Cell::new(0);
+            self.update(shard, |map| {
+                let retained: Map = map
+                    .iter()
+                    .filter(|(filter, node)| {
+                        !remove.contains(*filter) || node.used.load(Ordering::SeqCst) >= threshold
+                    })
+                    .map(|(filter, node)| (*filter, node.clone()))
+                    .collect();
+                count.set(map.len() - retained.len());
+                if retained.len() == map.len() {
+                    return None;
+                }
+                Some(retained)
+            });
+            removed += count.get();
+        }
+        removed
+    }
+}
+
+fn node(filter: Filter) -> Option<Arc<Node>> {
+    LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        let epoch = ARENA.epoch();
+        if local.0 != epoch {
+            *local = (epoch, HashMap::new());
+        }
+        if let Some(node) = local.1.get(&filter) {
+            return Some(node.clone());
+        }
+        let node = ARENA.node(filter)?;
+        local.1.insert(filter, node.clone());
+        Some(node)
+    })
+}
+
+pub(super) fn intern(op: Op) -> Filter {
+    ARENA.intern(op)
+}
+
+/// The op of `filter`, or `None` if it was removed by `collect`
+pub(super) fn op(filter: Filter) -> Option<Arc<Op>> {
+    node(filter).map(|node| node.op.clone())
+}
+
+pub(super) fn get_memo(filter: Filter, memo: Memo) -> Option<Filter> {
+    let result = node(filter)?.memos[memo as usize].get().copied()?;
+    // The result may have been removed while the filter itself was interned again
+    node(result).map(|_| result)
+}
+
+pub(super) fn set_memo(filter: Filter, memo: Memo, result: Filter) {
+    if let Some(node) = node(filter) {
+        node.memos[memo as usize].get_or_init(|| result);
+    }
+}
+
+pub(super) fn pin() -> Pin<'static> {
+    ARENA.pin()
+}
+
+/// Remove all filters that have not been used since the previous call, and neither between
+/// the two calls before, unless they are part of, or the memoized result of, a filter that
+/// has been used. Filters used while a `Pin` taken before is alive are kept as well.
+/// Filters must therefore not be held for longer than one period between two calls without
+/// a `Pin`, looking up a removed filter fails.
+/// Returns the number of filters removed.
+pub(super) fn collect() -> usize {
+    ARENA.collect()
+}
+
+/// Canonical encoding of an `Op`, used to compute its id. It must stay the same across
+/// versions for the ids to be stable, so new fields or ops may only be added with new tags.
+#[derive(Default)]
+struct Encoder {
+    bytes: Vec<u8>,
+    children: Vec<Filter>,
+}
+
+impl Encoder {
+    fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.len(bytes.len());
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+
+    fn len(&mut self, n: usize) -> &mut Self {
+        self.bytes.extend_from_slice(&(n as u64).to_le_bytes());
+        self
+    }
+
+    fn str(&mut self, s: &str) -> &mut Self {
+        self.raw(s.as_bytes())
+    }
+
+    fn path(&mut self, path: &Path) -> &mut Self {
+        use std::os::unix::ffi::OsStrExt;
+        self.raw(path.as_os_str().as_bytes())
+    }
+
+    fn filter(&mut self, filter: Filter) -> &mut Self {
+        self.bytes.extend_from_slice(filter.id().as_bytes());
+        self.children.push(filter);
+        self
+    }
+
+    fn op(&mut self, op: &Op) {
+        match op {
+            Op::Nop => self.str("nop"),
+            Op::Empty => self.str("empty"),
+            Op::Fold => self.str("fold"),
+            Op::Squash => self.str("squash"),
+            Op::SquashSelect(selectors) => {
+                self.str("squash_select").len(selectors.len());
+                for s in selectors {
+                    self.str(s);
+                }
+                self
+            }
+            Op::Since(cutoff) => self.str("since").str(cutoff),
+            Op::Linear => self.str("linear"),
+            Op::TextOnly => self.str("text_only"),
+            Op::Submodules => self.str("submodules"),
+            Op::Paths => self.str("paths"),
+            #[cfg(feature = "search")]
+            Op::Index => self.str("index"),
+            Op::Invert => self.str("invert"),
+            Op::File(path) => self.str("file").path(path),
+            Op::Prefix(path) => self.str("prefix").path(path),
+            Op::Subdir(path) => self.str("subdir").path(path),
+            Op::Follow(path) => self.str("follow").path(path),
+            Op::Workspace(path) => self.str("workspace").path(path),
+            Op::Include(path) => self.str("include").path(path),
+            Op::Mailmap(path) => self.str("mailmap").path(path),
+            Op::Patterns(path) => self.str("patterns").path(path),
+            Op::Owners(owner) => self.str("owners").str(owner),
+            Op::Glob(pattern) => self.str("glob").str(pattern),
+            Op::Plugin(name, args) => {
+                self.str("plugin").str(name).len(args.len());
+                for arg in args {
+                    self.str(arg);
+                }
+                self
+            }
+            Op::Size(size) => {
+                self.str("size");
+                self.bytes.extend_from_slice(&size.to_le_bytes());
+                self
+            }
+            Op::Compose(filters) => {
+                self.str("compose").len(filters.len());
+                for f in filters {
+                    self.filter(*f);
+                }
+                self
+            }
+            Op::Rev(filters) => {
+                self.str("rev").len(filters.len());
+                for (id, f) in filters {
+                    self.raw(id.as_bytes()).filter(*f);
+                }
+                self
+            }
+            Op::Replace(replacements) => {
+                self.str("replace").len(replacements.len());
+                for (regex, replacement) in replacements {
+                    self.str(regex.as_str()).str(replacement);
+                }
+                self
+            }
+            Op::Message(template, regexes) => {
+                self.str("message").str(template).len(regexes.len());
+                for regex in regexes {
+                    self.str(regex.as_str());
+                }
+                self
+            }
+            Op::DropCommits(conditions) => {
+                self.str("drop_commits").len(conditions.len());
+                for c in conditions {
+                    self.str(c.field()).str(c.pattern());
+                }
+                self
+            }
+            Op::Chain(a, b) => self.str("chain").filter(*a).filter(*b),
+            Op::Subtract(a, b) => self.str("subtract").filter(*a).filter(*b),
+            Op::Exclude(f) => self.str("exclude").filter(*f),
+            Op::Ref(name, f) => self.str("ref").str(name).filter(*f),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn subdir(arena: &Arena, path: &str) -> Filter {
+        arena.intern(Op::Subdir(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn intern_test() {
+        let arena = Arena::default();
+        let a = subdir(&arena, "a");
+        assert_eq!(a, subdir(&arena, "a"));
+        assert_ne!(a, subdir(&arena, "b"));
+        assert_eq!(
+            format!("{:?}", arena.node(a).unwrap().op),
+            format!("{:?}", Op::Subdir(PathBuf::from("a")))
+        );
+
+        let chain = arena.intern(Op::Chain(a, subdir(&arena, "b")));
+        assert_eq!(
+            arena.node(chain).unwrap().children,
+            vec![a, subdir(&arena, "b")]
+        );
+    }
+
+    #[test]
+    fn canonical_id_test() {
+        // Ids are persisted in the cache and must not change between versions
+        assert_eq!(
+            parse(":/a").unwrap().id().to_string(),
+            "d4d5dba6b0aa556c39057cc28434eec8b349b6c0"
+        );
+        assert_eq!(
+            parse(":[:/a,:/b]").unwrap(),
+            to_filter(Op::Compose(vec![
+                parse(":/a").unwrap(),
+                parse(":/b").unwrap()
+            ]))
+        );
+        assert_ne!(parse(":[:/a,:/b]").unwrap(), parse(":[:/b,:/a]").unwrap());
+        assert_ne!(parse(":/a").unwrap(), parse(":prefix=a").unwrap());
+
+        // Lengths are encoded, so the boundaries of strings can't be moved
+        assert_ne!(
+            parse(":replace(\"ab\":\"c\")").unwrap(),
+            parse(":replace(\"a\":\"bc\")").unwrap()
+        );
+    }
+
+    #[test]
+    fn collect_test() {
+        let arena = Arena::default();
+        let a = subdir(&arena, "a");
+        let b = subdir(&arena, "b");
+        let chain = arena.intern(Op::Chain(a, b));
+        let unused = subdir(&arena, "c");
+
+        // Everything was used since the previous call
+        assert_eq!(arena.collect(), 0);
+
+        // Filters are only removed when found unused twice in a row
+        arena.node(chain);
+        assert_eq!(arena.collect(), 0);
+        arena.node(chain);
+        assert_eq!(arena.collect(), 1);
+        assert!(arena.node(unused).is_none());
+
+        // The parts of used filters are kept
+        arena.node(a);
+        arena.node(b);
+        assert_eq!(arena.collect(), 0);
+        assert_eq!(arena.collect(), 1);
+        assert_eq!(arena.collect(), 2);
+
+        // Interning again brings a filter back
+        assert_eq!(subdir(&arena, "c"), unused);
+        assert!(arena.node(unused).is_some());
+    }
+
+    #[test]
+    fn pin_test() {
+        let arena = Arena::default();
+        let pin = arena.pin();
+        let a = subdir(&arena, "a");
+        assert_eq!(arena.collect(), 0);
+        assert_eq!(arena.collect(), 0);
+        assert_eq!(arena.collect(), 0);
+        arena.node(a);
+
+        drop(pin);
+        assert_eq!(arena.collect(), 0);
+        assert_eq!(arena.collect(), 0);
+        assert_eq!(arena.collect(), 1);
+    }
+
+    #[test]
+    fn concurrent_collect_test() {
+        let arena = Arena::default();
+        let done = std::sync::atomic::AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for t in 0..4 {
+                let arena = &arena;
+                let done = &done;
+                scope.spawn(move || {
+                    for i in 0..2000 {
+                        let _pin = arena.pin();
+                        let a = subdir(arena, &format!("{}", i % 50));
+                        let b = subdir(arena, &format!("{}", t));
+                        let chain = arena.intern(Op::Chain(a, b));
+                        std::thread::yield_now();
+                        assert_eq!(arena.node(chain).unwrap().children, vec![a, b]);
+                        assert!(arena.node(a).is_some());
+                    }
+                    done.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            while done.load(Ordering::Relaxed) < 4 {
+                arena.collect();
+            }
+        });
+    }
+}
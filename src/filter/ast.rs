@@ -0,0 +1,413 @@
+use super::*;
+
+/// Version of the JSON representation produced by `to_json`. It is increased whenever
+/// existing documents would be read differently, so stored filters can be checked.
+pub const AST_VERSION: u32 = 1;
+
+/// Structured representation of a filter, mirroring the filter syntax.
+/// Every filter that can be parsed can be converted to an `Ast` and back without loss.
+/// Paths are written as they are, with `\xNN` escapes only for bytes that are not valid
+/// UTF-8 and for backslashes.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Ast {
+    Nop,
+    Empty,
+    Fold,
+    Squash,
+    Linear,
+    TextOnly,
+    Submodules,
+    Paths,
+    #[cfg(feature = "search")]
+    Index,
+    Invert,
+
+    File {
+        path: String,
+    },
+    Prefix {
+        path: String,
+    },
+    Subdir {
+        path: String,
+    },
+    Follow {
+        path: String,
+    },
+    Workspace {
+        path: String,
+    },
+    Include {
+        path: String,
+    },
+    Mailmap {
+        path: String,
+    },
+    Patterns {
+        path: String,
+    },
+    Owners {
+        owner: String,
+    },
+
+    Glob {
+        pattern: String,
+    },
+    Plugin {
+        name: String,
+        args: Vec<String>,
+    },
+    Size {
+        max: u64,
+    },
+    SquashSelect {
+        selectors: Vec<String>,
+    },
+    Since {
+        cutoff: String,
+    },
+
+    Compose {
+        filters: Vec<Ast>,
+    },
+    Rev {
+        entries: Vec<RevEntry>,
+    },
+    Replace {
+        replacements: Vec<Replacement>,
+    },
+    Message {
+        template: String,
+        strip: Vec<String>,
+    },
+    DropCommits {
+        conditions: Vec<Condition>,
+    },
+    Chain {
+        first: Box<Ast>,
+        second: Box<Ast>,
+    },
+    Subtract {
+        filter: Box<Ast>,
+        subtract: Box<Ast>,
+    },
+    Exclude {
+        filter: Box<Ast>,
+    },
+    Ref {
+        name: String,
+        filter: Box<Ast>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RevEntry {
+    /// A full commit id or `_` for the default filter
+    pub rev: String,
+    pub filter: Ast,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Replacement {
+    pub regex: String,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Condition {
+    /// One of `author`, `committer` or `message`
+    pub field: String,
+    pub pattern: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Document {
+    version: u32,
+    filter: Ast,
+}
+
+/// JSON strings can hold any valid UTF-8, so only bytes that are not valid UTF-8 and
+/// backslashes, which would be read as the start of an escape, need the `\xNN` form
+fn path(p: &Path) -> String {
+    escape_path_with(p, |c| c != '\\')
+}
+
+fn boxed(filter: Filter) -> Box<Ast> {
+    Box::new(to_ast(filter))
+}
+
+/// Convert a filter into its structured representation
+pub fn to_ast(filter: Filter) -> Ast {
+    match to_op_owned(filter) {
+        Op::Nop => Ast::Nop,
+        Op::Empty => Ast::Empty,
+        Op::Fold => Ast::Fold,
+        Op::Squash => Ast::Squash,
+        Op::Linear => Ast::Linear,
+        Op::TextOnly => Ast::TextOnly,
+        Op::Submodules => Ast::Submodules,
+        Op::Paths => Ast::Paths,
+        #[cfg(feature = "search")]
+        Op::Index => Ast::Index,
+        Op::Invert => Ast::Invert,
+
+        Op::File(p) => Ast::File { path: path(&p) },
+        Op::Prefix(p) => Ast::Prefix { path: path(&p) },
+        Op::Subdir(p) => Ast::Subdir { path: path(&p) },
+        Op::Follow(p) => Ast::Follow { path: path(&p) },
+        Op::Workspace(p) => Ast::Workspace { path: path(&p) },
+        Op::Include(p) => Ast::Include { path: path(&p) },
+        Op::Mailmap(p) => Ast::Mailmap { path: path(&p) },
+        Op::Patterns(p) => Ast::Patterns { path: path(&p) },
+        Op::Owners(owner) => Ast::Owners { owner },
+
+        Op::Glob(pattern) => Ast::Glob { pattern },
+        Op::Plugin(name, args) => Ast::Plugin { name, args },
+        Op::Size(max) => Ast::Size { max },
+        Op::SquashSelect(selectors) => Ast::SquashSelect { selectors },
+        Op::Since(cutoff) => Ast::Since { cutoff },
+
+        Op::Compose(filters) => Ast::Compose {
+            filters: filters.into_iter().map(to_ast).collect(),
+        },
+        Op::Rev(filters) => Ast::Rev {
+            entries: filters
+                .into_iter()
+                .map(|(id, f)| RevEntry {
+                    rev: if id == git2::Oid::zero() {
+                        "_".to_string()
+                    } else {
+                        id.to_string()
+                    },
+                    filter: to_ast(f),
+                })
+                .collect(),
+        },
+        Op::Replace(replacements) => Ast::Replace {
+            replacements: replacements
+                .into_iter()
+                .map(|(regex, replacement)| Replacement {
+                    regex: regex.as_str().to_string(),
+                    replacement,
+                })
+                .collect(),
+        },
+        Op::Message(template, strip) => Ast::Message {
+            template,
+            strip: strip.iter().map(|r| r.as_str().to_string()).collect(),
+        },
+        Op::DropCommits(conditions) => Ast::DropCommits {
+            conditions: conditions
+                .iter()
+                .map(|c| Condition {
+                    field: c.field().to_string(),
+                    pattern: c.pattern().to_string(),
+                })
+                .collect(),
+        },
+        Op::Chain(a, b) => Ast::Chain {
+            first: boxed(a),
+            second: boxed(b),
+        },
+        Op::Subtract(a, b) => Ast::Subtract {
+            filter: boxed(a),
+            subtract: boxed(b),
+        },
+        Op::Exclude(b) => Ast::Exclude { filter: boxed(b) },
+        Op::Ref(name, f) => Ast::Ref {
+            name,
+            filter: boxed(f),
+        },
+    }
+}
+
+fn regex(s: &str) -> JoshResult<regex::Regex> {
+    regex::Regex::new(s).map_err(|e| josh_error(&format!("invalid regex {:?}: {}", s, e)))
+}
+
+fn from_ast2(ast: &Ast) -> JoshResult<Filter> {
+    let op = match ast {
+        Ast::Nop => Op::Nop,
+        Ast::Empty => Op::Empty,
+        Ast::Fold => Op::Fold,
+        Ast::Squash => Op::Squash,
+        Ast::Linear => Op::Linear,
+        Ast::TextOnly => Op::TextOnly,
+        Ast::Submodules => Op::Submodules,
+        Ast::Paths => Op::Paths,
+        #[cfg(feature = "search")]
+        Ast::Index => Op::Index,
+        Ast::Invert => Op::Invert,
+
+        Ast::File { path } => Op::File(unescape_path(path)),
+        Ast::Prefix { path } => Op::Prefix(unescape_path(path)),
+        Ast::Subdir { path } => Op::Subdir(unescape_path(path)),
+        Ast::Follow { path } => Op::Follow(unescape_path(path)),
+        Ast::Workspace { path } => Op::Workspace(unescape_path(path)),
+        Ast::Include { path } => Op::Include(unescape_path(path)),
+        Ast::Mailmap { path } => Op::Mailmap(unescape_path(path)),
+        Ast::Patterns { path } => Op::Patterns(unescape_path(path)),
+        Ast::Owners { owner } => Op::Owners(owner.clone()),
+
+        Ast::Glob { pattern } => Op::Glob(pattern.clone()),
+        Ast::Plugin { name, args } => {
+            if !plugin::valid_name(name) {
+                return Err(josh_error(&format!("invalid plugin name: {:?}", name)));
+            }
+            Op::Plugin(name.clone(), args.clone())
+        }
+        Ast::Size { max } => Op::Size(*max),
+        Ast::SquashSelect { selectors } => Op::SquashSelect(selectors.clone()),
+        Ast::Since { cutoff } => Op::Since(cutoff.clone()),
+
+        Ast::Compose { filters } => Op::Compose(
+            filters
+                .iter()
+                .map(from_ast2)
+                .collect::<JoshResult<Vec<_>>>()?,
+        ),
+        Ast::Rev { entries } => Op::Rev(
+            entries
+                .iter()
+                .map(|e| {
+                    let id = if e.rev == "_" {
+                        git2::Oid::zero()
+                    } else {
+                        git2::Oid::from_str(&e.rev)?
+                    };
+                    Ok((id, from_ast2(&e.filter)?))
+                })
+                .collect::<JoshResult<Vec<_>>>()?,
+        ),
+        Ast::Replace { replacements } => Op::Replace(
+            replacements
+                .iter()
+                .map(|r| Ok((regex(&r.regex)?, r.replacement.clone())))
+                .collect::<JoshResult<Vec<_>>>()?,
+        ),
+        Ast::Message { template, strip } => Op::Message(
+            template.clone(),
+            strip
+                .iter()
+                .map(|s| regex(s))
+                .collect::<JoshResult<Vec<_>>>()?,
+        ),
+        Ast::DropCommits { conditions } => Op::DropCommits(
+            conditions
+                .iter()
+                .map(|c| CommitMatch::new(&c.field, &c.pattern).map_err(|e| josh_error(&e)))
+                .collect::<JoshResult<Vec<_>>>()?,
+        ),
+        Ast::Chain { first, second } => Op::Chain(from_ast2(first)?, from_ast2(second)?),
+        Ast::Subtract { filter, subtract } => {
+            Op::Subtract(from_ast2(filter)?, from_ast2(subtract)?)
+        }
+        Ast::Exclude { filter } => Op::Exclude(from_ast2(filter)?),
+        Ast::Ref { name, filter } => Op::Ref(name.clone(), from_ast2(filter)?),
+    };
+    Ok(to_filter(op))
+}
+
+/// Create a filter from its structured representation
+pub fn from_ast(ast: &Ast) -> JoshResult<Filter> {
+    Ok(opt::optimize(from_ast2(ast)?))
+}
+
+/// Serialize the filter as a versioned JSON document:
+/// `{"version": AST_VERSION, "filter": <Ast>}`
+pub fn to_json(filter: Filter) -> String {
+    let document = Document {
+        version: AST_VERSION,
+        filter: to_ast(filter),
+    };
+    serde_json::to_string_pretty(&document).expect("serialize filter")
+}
+
+/// Read a filter serialized with `to_json`
+pub fn from_json(json: &str) -> JoshResult<Filter> {
+    let document: Document = serde_json::from_str(json)?;
+    if document.version != AST_VERSION {
+        return Err(josh_error(&format!(
+            "unsupported filter version {} (expected {})",
+            document.version, AST_VERSION
+        )));
+    }
+    from_ast(&document.filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_test() {
+        for spec in &[
+            ":/",
+            ":/a/b:prefix=c",
+            ":/caf\\xE9:prefix=a\\x20b",
+            ":/a\\x5Cb",
+            ":follow=/a/b",
+            ":[a=:/x::y/,a/b=:/i]:prefix=c",
+            ":exclude[::*.md]:subtract[:/a,:/b]",
+            ":size<1M:text_only:submodules:linear:since=2015-01-01",
+            ":plugin=foo,x,y:mailmap=.mailmap:include=f.josh:patterns=a/.view",
+            r#":replace("a+":"b","\"":"'")"#,
+            r#":message("{summary}\n\n{body}","^Ticket:")"#,
+            r#":drop_commits(author=bot@*,message=^\[skip-view\])"#,
+            ":rev(0000000000000000000000000000000000000001:/a,_:/b)",
+            ":workspace=ws:owners=@org/team-a",
+            ":squash=refs/tags/v*,0000000000000000000000000000000000000001",
+            "let x = :/a\nb = :@x\nc = :@x\n",
+        ] {
+            let filter = parse(spec).unwrap();
+            let json = to_json(filter);
+            assert_eq!(filter, from_json(&json).unwrap(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn json_format_test() {
+        let filter = parse(":/a:prefix=b").unwrap();
+        let expected = serde_json::json!({
+            "version": 1,
+            "filter": {
+                "op": "chain",
+                "first": { "op": "subdir", "path": "a" },
+                "second": { "op": "prefix", "path": "b" },
+            },
+        });
+        assert_eq!(
+            expected,
+            serde_json::from_str::<serde_json::Value>(&to_json(filter)).unwrap()
+        );
+
+        let filter = parse(":/caf\\xC3\\xA9\\x20\\xE9:prefix=a\\x5Cb").unwrap();
+        let expected = serde_json::json!({
+            "version": 1,
+            "filter": {
+                "op": "chain",
+                "first": { "op": "subdir", "path": "caf\u{e9} \\xE9" },
+                "second": { "op": "prefix", "path": "a\\x5Cb" },
+            },
+        });
+        assert_eq!(
+            expected,
+            serde_json::from_str::<serde_json::Value>(&to_json(filter)).unwrap()
+        );
+    }
+
+    #[test]
+    fn version_test() {
+        assert!(from_json(r#"{"version": 2, "filter": {"op": "nop"}}"#).is_err());
+        assert!(from_json(r#"{"version": 1, "filter": {"op": "unknown"}}"#).is_err());
+        assert_eq!(
+            parse(":/a").unwrap(),
+            from_json(r#"{"version": 1, "filter": {"op": "subdir", "path": "a"}}"#).unwrap()
+        );
+        assert_eq!(
+            parse(":/caf\\xE9").unwrap(),
+            from_json(r#"{"version": 1, "filter": {"op": "subdir", "path": "caf\\xE9"}}"#).unwrap()
+        );
+    }
+}
@@ -1,17 +1,20 @@
 use super::*;
 use pest::Parser;
 use std::path::Path;
+use std::sync::Arc;
+mod arena;
+mod ast;
 mod opt;
 mod parse;
+mod patterns;
+mod plugin;
 pub mod tree;
 
+pub use ast::{
+    from_ast, from_json, to_ast, to_json, Ast, Condition, Replacement, RevEntry, AST_VERSION,
+};
 pub use parse::get_comments;
-pub use parse::parse;
-
-lazy_static! {
-    static ref FILTERS: std::sync::Mutex<std::collections::HashMap<Filter, Op>> =
-        std::sync::Mutex::new(std::collections::HashMap::new());
-}
+pub use parse::{parse, try_parse, ParseError};
 
 /// Filters are represented as `git2::Oid`, however they are not ever stored
 /// inside the repo.
@@ -26,7 +29,10 @@ impl Filter {
 
 impl std::fmt::Debug for Filter {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        return to_op(*self).fmt(f);
+        match arena::op(*self) {
+            Some(op) => op.fmt(f),
+            None => write!(f, "<unknown filter {}>", self.0),
+        }
     }
 }
 
@@ -39,21 +45,50 @@ pub fn empty() -> Filter {
 }
 
 fn to_filter(op: Op) -> Filter {
-    let s = format!("{:?}", op);
-    let f = Filter(
-        git2::Oid::hash_object(git2::ObjectType::Blob, s.as_bytes()).expect("hash_object filter"),
-    );
-    FILTERS.lock().unwrap().insert(f, op);
-    f
+    arena::intern(op)
+}
+
+/// Only for filters that are known to be in use, others are checked by `to_op_checked`
+fn to_op(filter: Filter) -> Arc<Op> {
+    arena::op(filter).expect("filter was removed while in use")
+}
+
+/// Like `to_op`, for filters passed in by callers, which may have held them for too long
+/// without a `Pin`
+fn to_op_checked(filter: Filter) -> JoshResult<Arc<Op>> {
+    arena::op(filter).ok_or_else(|| {
+        josh_error(&format!(
+            "unknown filter {}, it was removed as it was not pinned",
+            filter.id()
+        ))
+    })
+}
+
+/// Whether `filter` can still be used, filters held for too long without a `Pin` are removed
+/// by `collect`
+pub fn is_known(filter: Filter) -> bool {
+    arena::op(filter).is_some()
+}
+
+/// Like `to_op`, for taking the op apart
+fn to_op_owned(filter: Filter) -> Op {
+    to_op(filter).as_ref().clone()
 }
 
-fn to_op(filter: Filter) -> Op {
-    FILTERS
-        .lock()
-        .unwrap()
-        .get(&filter)
-        .expect("unknown filter")
-        .clone()
+/// Free filters that are no longer in use, see `arena::collect`.
+/// Returns the number of filters removed.
+pub fn collect() -> usize {
+    arena::collect()
+}
+
+pub use arena::Pin;
+pub use plugin::{allow_plugins, set_plugin_timeout};
+
+/// Keep filters from being freed by `collect` while the returned guard is alive.
+/// Must be taken before obtaining any filter that is held for longer than the
+/// period between two calls of `collect`.
+pub fn pin() -> Pin<'static> {
+    arena::pin()
 }
 
 #[derive(Clone, Debug)]
@@ -62,6 +97,11 @@ enum Op {
     Empty,
     Fold,
     Squash,
+    SquashSelect(Vec<String>),
+    Since(String),
+    Linear,
+    TextOnly,
+    Submodules,
     Paths,
 
     #[cfg(feature = "search")]
@@ -71,14 +111,29 @@ enum Op {
     File(std::path::PathBuf),
     Prefix(std::path::PathBuf),
     Subdir(std::path::PathBuf),
+    Follow(std::path::PathBuf),
     Workspace(std::path::PathBuf),
+    Include(std::path::PathBuf),
+    Mailmap(std::path::PathBuf),
+    Patterns(std::path::PathBuf),
+    Owners(String),
 
     Glob(String),
+    Plugin(String, Vec<String>),
+    Size(u64),
 
     Compose(Vec<Filter>),
+    Rev(Vec<(git2::Oid, Filter)>),
+    Replace(Vec<(regex::Regex, String)>),
+    Message(String, Vec<regex::Regex>),
+    DropCommits(Vec<CommitMatch>),
     Chain(Filter, Filter),
     Subtract(Filter, Filter),
     Exclude(Filter),
+
+    /// Reference to a filter bound with `let` in a workspace file. Behaves exactly like the
+    /// bound filter, the name is only kept for printing.
+    Ref(String, Filter),
 }
 
 /// Pretty print the filter on multiple lines with initial indentation level.
@@ -86,19 +141,65 @@ enum Op {
 pub fn pretty(filter: Filter, indent: usize) -> String {
     let filter = opt::simplify(filter);
 
-    if let Op::Compose(filters) = to_op(filter) {
-        if indent == 0 {
+    if indent == 0 {
+        let mut bindings = vec![];
+        collect_bindings(filter, &mut bindings);
+        let mut lets: String = bindings
+            .iter()
+            .map(|(name, f)| {
+                format!(
+                    "let {} = {}\n",
+                    name,
+                    pretty2(&to_op(opt::simplify(*f)), indent, false)
+                )
+            })
+            .collect();
+        if !lets.is_empty() {
+            lets.push('\n');
+        }
+
+        if let Op::Compose(filters) = to_op(filter).as_ref() {
             let i = format!("\n{}", " ".repeat(indent));
-            return filters
+            let joined = filters
                 .iter()
                 .map(|x| pretty2(&to_op(*x), indent + 4, true))
                 .collect::<Vec<_>>()
                 .join(&i);
+            return format!("{}{}", lets, joined);
         }
+        return format!("{}{}", lets, pretty2(&to_op(filter), indent, true));
     }
     pretty2(&to_op(filter), indent, true)
 }
 
+/// Find all `Op::Ref` in `filter`, such that bindings come after the ones they refer to.
+fn collect_bindings(filter: Filter, bindings: &mut Vec<(String, Filter)>) {
+    match to_op_owned(filter) {
+        Op::Ref(name, f) => {
+            collect_bindings(f, bindings);
+            if !bindings.iter().any(|(n, _)| *n == name) {
+                bindings.push((name, f));
+            }
+        }
+        Op::Compose(filters) => {
+            for f in filters {
+                collect_bindings(f, bindings);
+            }
+        }
+        Op::Rev(filters) => {
+            for (_, f) in filters {
+                collect_bindings(f, bindings);
+            }
+        }
+        Op::Chain(a, b) | Op::Subtract(a, b) => {
+            collect_bindings(a, bindings);
+            collect_bindings(b, bindings);
+        }
+        Op::Exclude(b) => collect_bindings(b, bindings),
+        _ => {}
+    }
+}
+
 fn pretty2(op: &Op, indent: usize, compose: bool) -> String {
     let ff = |filters: &Vec<_>, n, ind| {
         let ind2 = std::cmp::max(ind, 4);
@@ -120,23 +221,20 @@ fn pretty2(op: &Op, indent: usize, compose: bool) -> String {
     match op {
         Op::Compose(filters) => ff(filters, "", indent),
         Op::Subtract(af, bf) => ff(&vec![*af, *bf], "subtract", indent + 4),
-        Op::Exclude(bf) => match to_op(*bf) {
+        Op::Exclude(bf) => match to_op_owned(*bf) {
             Op::Compose(filters) => ff(&filters, "exclude", indent),
             b => format!(":exclude[{}]", pretty2(&b, indent, false)),
         },
-        Op::Chain(a, b) => match (to_op(*a), to_op(*b)) {
+        Op::Chain(a, b) => match (to_op(*a).as_ref(), to_op(*b).as_ref()) {
             (Op::Subdir(p1), Op::Prefix(p2)) if p1 == p2 => {
-                format!("::{}/", p1.to_string_lossy())
+                format!("::{}/", escape_path(p1))
             }
             (a, Op::Prefix(p)) if compose => {
-                format!("{} = {}", p.to_string_lossy(), pretty2(&a, indent, false))
+                format!("{} = {}", escape_path(p), pretty2(a, indent, false))
             }
-            (a, b) => format!(
-                "{}{}",
-                pretty2(&a, indent, false),
-                pretty2(&b, indent, false)
-            ),
+            (a, b) => format!("{}{}", pretty2(a, indent, false), pretty2(b, indent, false)),
         },
+        Op::Ref(name, _) => format!(":@{}", name),
         _ => spec2(op),
     }
 }
@@ -166,15 +264,75 @@ fn spec2(op: &Op) -> String {
         Op::Exclude(b) => {
             format!(":exclude[{}]", spec(*b))
         }
+        Op::Ref(_, f) => spec(*f),
         Op::Workspace(path) => {
-            format!(":workspace={}", path.to_string_lossy())
+            format!(":workspace={}", escape_path(path))
+        }
+        Op::Include(path) => {
+            format!(":include={}", escape_path(path))
+        }
+        Op::Mailmap(path) => {
+            format!(":mailmap={}", escape_path(path))
+        }
+        Op::Patterns(path) => {
+            format!(":patterns={}", escape_path(path))
+        }
+        Op::Owners(owner) => {
+            format!(":owners={}", owner)
+        }
+        Op::Rev(filters) => {
+            format!(
+                ":rev({})",
+                filters
+                    .iter()
+                    .map(|(id, f)| if *id == git2::Oid::zero() {
+                        format!("_{}", spec(*f))
+                    } else {
+                        format!("{}{}", id, spec(*f))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )
+        }
+        Op::Replace(replacements) => {
+            format!(
+                ":replace({})",
+                replacements
+                    .iter()
+                    .map(|(regex, replacement)| format!(
+                        "{}:{}",
+                        quote(regex.as_str()),
+                        quote(replacement)
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )
+        }
+        Op::DropCommits(conditions) => {
+            format!(
+                ":drop_commits({})",
+                conditions
+                    .iter()
+                    .map(|c| format!("{}={}", c.field(), quote(c.pattern())))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )
+        }
+        Op::Message(template, strip) => {
+            format!(
+                ":message({})",
+                std::iter::once(quote(template).replace('\n', "\\n"))
+                    .chain(strip.iter().map(|regex| quote(regex.as_str())))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )
         }
 
-        Op::Chain(a, b) => match (to_op(*a), to_op(*b)) {
+        Op::Chain(a, b) => match (to_op(*a).as_ref(), to_op(*b).as_ref()) {
             (Op::Subdir(p1), Op::Prefix(p2)) if p1 == p2 => {
-                format!("::{}/", p1.to_string_lossy())
+                format!("::{}/", escape_path(p1))
             }
-            (a, b) => format!("{}{}", spec2(&a), spec2(&b)),
+            (a, b) => format!("{}{}", spec2(a), spec2(b)),
         },
 
         Op::Nop => ":/".to_string(),
@@ -185,11 +343,102 @@ fn spec2(op: &Op) -> String {
         Op::Index => ":INDEX".to_string(),
         Op::Fold => ":FOLD".to_string(),
         Op::Squash => ":SQUASH".to_string(),
-        Op::Subdir(path) => format!(":/{}", path.to_string_lossy()),
-        Op::File(path) => format!("::{}", path.to_string_lossy()),
-        Op::Prefix(path) => format!(":prefix={}", path.to_string_lossy()),
+        Op::SquashSelect(selectors) => format!(":squash={}", selectors.join(",")),
+        Op::Linear => ":linear".to_string(),
+        Op::Since(cutoff) => format!(":since={}", cutoff),
+        Op::Subdir(path) => format!(":/{}", escape_path(path)),
+        Op::Follow(path) => format!(":follow=/{}", escape_path(path)),
+        Op::File(path) => format!("::{}", escape_path(path)),
+        Op::Prefix(path) => format!(":prefix={}", escape_path(path)),
         Op::Glob(pattern) => format!("::{}", pattern),
+        Op::Plugin(name, args) => format!(
+            ":plugin={}",
+            std::iter::once(name)
+                .chain(args.iter())
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        Op::Size(limit) => format!(":size<{}", format_size(*limit)),
+        Op::TextOnly => ":text_only".to_string(),
+        Op::Submodules => ":submodules".to_string(),
+    }
+}
+
+fn format_size(size: u64) -> String {
+    for (unit, factor) in [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)] {
+        if size != 0 && size.is_multiple_of(factor) {
+            return format!("{}{}", size / factor, unit);
+        }
     }
+    size.to_string()
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Format a path for use in a filter spec. Bytes that can't be part of a path in the filter
+/// syntax, including those of names that are not valid UTF-8, are written as `\xNN`.
+fn escape_path(path: &Path) -> String {
+    escape_path_with(path, |c| {
+        c.is_ascii_alphanumeric() || "_-+.@/".contains(c) || !c.is_ascii()
+    })
+}
+
+/// Like `escape_path`, but keeps all characters for which `keep` returns true
+fn escape_path_with(path: &Path, keep: fn(char) -> bool) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    let mut result = String::new();
+    let mut bytes = path.as_os_str().as_bytes();
+    while !bytes.is_empty() {
+        let (valid, invalid) = match std::str::from_utf8(bytes) {
+            Ok(valid) => (valid, &[][..]),
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                let invalid = &rest[..e.error_len().unwrap_or(rest.len())];
+                (std::str::from_utf8(valid).unwrap_or_default(), invalid)
+            }
+        };
+        for c in valid.chars() {
+            if keep(c) {
+                result.push(c);
+            } else {
+                result.push_str(&format!("\\x{:02X}", c as u32));
+            }
+        }
+        for b in invalid {
+            result.push_str(&format!("\\x{:02X}", b));
+        }
+        bytes = &bytes[valid.len() + invalid.len()..];
+    }
+    result
+}
+
+/// Read a path written by `escape_path`
+fn unescape_path(s: &str) -> std::path::PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    let mut bytes = vec![];
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let escaped = tail
+            .strip_prefix(b"x")
+            .filter(|_| b == b'\\')
+            .and_then(|x| x.get(..2))
+            .and_then(|x| std::str::from_utf8(x).ok())
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match escaped {
+            Some(b) => {
+                bytes.push(b);
+                rest = &tail[3..];
+            }
+            None => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    std::ffi::OsString::from_vec(bytes).into()
 }
 
 pub fn src_path(filter: Filter) -> std::path::PathBuf {
@@ -218,6 +467,36 @@ fn dst_path2(op: &Op) -> std::path::PathBuf {
     })
 }
 
+/// Resolve the selectors of the `:squash=...` filter `filter` to a sorted list of commit ids.
+/// Selectors are either full commit ids or patterns of refs, which are looked up relative
+/// to the ref prefix of the transaction.
+fn squash_ids(
+    transaction: &cache::Transaction,
+    filter: Filter,
+    selectors: &[String],
+) -> JoshResult<Vec<String>> {
+    if let Some(ids) = transaction.get_squash_ids(filter) {
+        return Ok(ids);
+    }
+    let repo = transaction.repo();
+    let mut ids = vec![];
+    for selector in selectors {
+        if selector.starts_with("refs/") {
+            for reference in repo.references_glob(&transaction.refname(selector))? {
+                if let Ok(commit) = reference?.peel_to_commit() {
+                    ids.push(commit.id().to_string());
+                }
+            }
+        } else {
+            ids.push(selector.clone());
+        }
+    }
+    ids.sort();
+    ids.dedup();
+    transaction.insert_squash_ids(filter, ids.clone());
+    Ok(ids)
+}
+
 /// Calculate the filtered commit for `commit`. This can take some time if done
 /// for the first time and thus should generally be done asynchronously.
 pub fn apply_to_commit(
@@ -225,15 +504,22 @@ pub fn apply_to_commit(
     commit: &git2::Commit,
     transaction: &cache::Transaction,
 ) -> JoshResult<git2::Oid> {
-    let filter = opt::optimize(filter);
+    to_op_checked(filter)?;
+    let optimized = opt::optimize(opt::unref(filter));
+    let filter = opt::optimize_cached(transaction, filter);
     for _ in 0..10000 {
         let filtered = apply_to_commit2(&to_op(filter), commit, transaction)?;
 
         if let Some(id) = filtered {
+            // The chosen form depends on the state of the cache, storing the result for the
+            // canonical form as well makes it available regardless of that
+            if filter != optimized {
+                transaction.insert(optimized, commit.id(), id, true)?;
+            }
             return Ok(id);
         }
 
-        for (f, i) in transaction.get_missing() {
+        for (f, i) in transaction.get_missing()? {
             history::walk2(f, i, transaction)?;
         }
     }
@@ -246,7 +532,7 @@ pub fn apply_to_commit3(
     commit: &git2::Commit,
     transaction: &cache::Transaction,
 ) -> JoshResult<bool> {
-    Ok(apply_to_commit2(&to_op(filter), commit, transaction)?.is_some())
+    Ok(apply_to_commit2(&*to_op_checked(filter)?, commit, transaction)?.is_some())
 }
 
 fn apply_to_commit2(
@@ -271,11 +557,31 @@ fn apply_to_commit2(
                 return Ok(Some(git2::Oid::zero()));
             }
         }
+        Op::Ref(_, f) => return apply_to_commit2(&to_op(*f), commit, transaction),
         Op::Squash => {
-            return Some(history::rewrite_commit(repo, commit, &[], &commit.tree()?)).transpose()
+            return Some(history::rewrite_commit(
+                repo,
+                commit,
+                &[],
+                &commit.tree()?,
+                &history::CommitMeta::default(),
+            ))
+            .transpose()
+        }
+        Op::SquashSelect(selectors) => {
+            // Ref patterns are resolved first, so the cached results are for a fixed set
+            // of commits. Each new tag thus results in a different filter, which has to
+            // be computed for the whole history again.
+            let ids = squash_ids(transaction, filter, selectors)?;
+            if &ids != selectors {
+                return apply_to_commit2(&Op::SquashSelect(ids), commit, transaction);
+            }
+            if let Some(oid) = transaction.get(filter, commit.id())? {
+                return Ok(Some(oid));
+            }
         }
         _ => {
-            if let Some(oid) = transaction.get(filter, commit.id()) {
+            if let Some(oid) = transaction.get(filter, commit.id())? {
                 return Ok(Some(oid));
             }
         }
@@ -283,7 +589,7 @@ fn apply_to_commit2(
 
     rs_tracing::trace_scoped!("apply_to_commit", "spec": spec(filter), "commit": commit.id().to_string());
 
-    let filtered_tree = match &to_op(filter) {
+    let filtered_tree = match &*to_op(filter) {
         Op::Compose(filters) => {
             let filtered = filters
                 .iter()
@@ -302,20 +608,17 @@ fn apply_to_commit2(
 
             tree::compose(transaction, filtered)?
         }
-        Op::Workspace(ws_path) => {
-            let normal_parents = commit
-                .parent_ids()
-                .map(|parent| transaction.get(filter, parent))
-                .collect::<Option<Vec<git2::Oid>>>();
+        Op::Workspace(_) | Op::Include(_) => {
+            // Changes to the file the filter is read from add the history of
+            // newly mapped content as extra parents.
+            let spec_path = filter_file(op);
+
+            let normal_parents = transaction.get_all(filter, commit.parent_ids())?;
 
             let normal_parents = some_or!(normal_parents, { return Ok(None) });
 
-            let cw = parse::parse(&tree::get_blob(
-                repo,
-                &commit.tree()?,
-                &ws_path.join("workspace.josh"),
-            ))
-            .unwrap_or(to_filter(Op::Empty));
+            let cw = parse::parse(&tree::get_blob(repo, &commit.tree()?, &spec_path))
+                .unwrap_or(to_filter(Op::Empty));
 
             let extra_parents = commit
                 .parents()
@@ -324,7 +627,7 @@ fn apply_to_commit2(
                     let pcw = parse::parse(&tree::get_blob(
                         repo,
                         &parent.tree().unwrap_or(tree::empty(repo)),
-                        &ws_path.join("workspace.josh"),
+                        &spec_path,
                     ))
                     .unwrap_or(to_filter(Op::Empty));
 
@@ -351,14 +654,145 @@ fn apply_to_commit2(
                 filtered_tree,
                 transaction,
                 filter,
+                history::CommitMeta::default(),
+            ))
+            .transpose();
+        }
+        Op::Mailmap(path) => {
+            let filtered_parent_ids = transaction.get_all(filter, commit.parent_ids())?;
+
+            let filtered_parent_ids = some_or!(filtered_parent_ids, { return Ok(None) });
+
+            let mailmap = git2::Mailmap::from_buffer(&tree::get_blob(repo, &commit.tree()?, path))?;
+            let meta = history::CommitMeta {
+                author: Some(mailmap.resolve_signature(&commit.author())?),
+                committer: Some(mailmap.resolve_signature(&commit.committer())?),
+                ..Default::default()
+            };
+
+            return Some(history::create_filtered_commit(
+                commit,
+                filtered_parent_ids,
+                commit.tree()?,
+                transaction,
+                filter,
+                meta,
+            ))
+            .transpose();
+        }
+        Op::Message(template, strip) => {
+            let filtered_parent_ids = transaction.get_all(filter, commit.parent_ids())?;
+
+            let filtered_parent_ids = some_or!(filtered_parent_ids, { return Ok(None) });
+
+            let meta = history::CommitMeta {
+                message: Some(rewrite_message(commit, template, strip)),
+                ..Default::default()
+            };
+
+            return Some(history::create_filtered_commit(
+                commit,
+                filtered_parent_ids,
+                commit.tree()?,
+                transaction,
+                filter,
+                meta,
+            ))
+            .transpose();
+        }
+        Op::Linear => {
+            let filtered_parent_ids = transaction.get_all(filter, commit.parent_ids().take(1))?;
+
+            let filtered_parent_ids = some_or!(filtered_parent_ids, { return Ok(None) });
+
+            let meta = if commit.parent_count() > 1 {
+                history::CommitMeta {
+                    message: Some(linear_message(repo, commit)?),
+                    ..Default::default()
+                }
+            } else {
+                history::CommitMeta::default()
+            };
+
+            return Some(history::create_filtered_commit(
+                commit,
+                filtered_parent_ids,
+                commit.tree()?,
+                transaction,
+                filter,
+                meta,
+            ))
+            .transpose();
+        }
+        Op::DropCommits(conditions) => {
+            let filtered_parent_ids = transaction.get_all(filter, commit.parent_ids())?;
+
+            let filtered_parent_ids = some_or!(filtered_parent_ids, { return Ok(None) });
+
+            // Dropped commits are replaced by their filtered parent, unless they merge
+            // different histories. Remaining commits keep their original tree.
+            if conditions.iter().all(|c| c.matches(commit)) {
+                let mut parents = filtered_parent_ids
+                    .iter()
+                    .filter(|x| **x != git2::Oid::zero())
+                    .collect::<Vec<_>>();
+                parents.dedup();
+                if parents.len() <= 1 {
+                    let id = parents.first().map_or(git2::Oid::zero(), |x| **x);
+                    transaction.insert(filter, commit.id(), id, commit.parent_count() != 1)?;
+                    return Ok(Some(id));
+                }
+            }
+
+            return Some(history::create_filtered_commit(
+                commit,
+                filtered_parent_ids,
+                commit.tree()?,
+                transaction,
+                filter,
+                history::CommitMeta::default(),
             ))
             .transpose();
         }
+        Op::SquashSelect(ids) => {
+            let filtered_parent_ids = transaction.get_all(filter, commit.parent_ids())?;
+
+            let filtered_parent_ids = some_or!(filtered_parent_ids, { return Ok(None) });
+
+            let mut filtered_parent_ids = filtered_parent_ids
+                .into_iter()
+                .filter(|x| *x != git2::Oid::zero())
+                .collect::<Vec<_>>();
+            let mut seen = std::collections::HashSet::new();
+            filtered_parent_ids.retain(|x| seen.insert(*x));
+
+            if ids.binary_search(&commit.id().to_string()).is_ok() {
+                return Some(history::create_filtered_commit(
+                    commit,
+                    filtered_parent_ids,
+                    commit.tree()?,
+                    transaction,
+                    filter,
+                    history::CommitMeta::default(),
+                ))
+                .transpose();
+            }
+
+            // Commits that are not selected are dropped, unless they merge
+            // different selected commits.
+            if filtered_parent_ids.len() <= 1 {
+                let id = filtered_parent_ids
+                    .first()
+                    .cloned()
+                    .unwrap_or(git2::Oid::zero());
+                transaction.insert(filter, commit.id(), id, commit.parent_count() != 1)?;
+                return Ok(Some(id));
+            }
+
+            repo.find_commit(filtered_parent_ids[0])?.tree()?
+        }
         Op::Fold => {
-            let filtered_parent_ids = commit
-                .parents()
-                .map(|x| transaction.get(filter, x.id()))
-                .collect::<Option<Vec<_>>>();
+            let filtered_parent_ids = transaction.get_all(filter, commit.parent_ids())?;
 
             let filtered_parent_ids = some_or!(filtered_parent_ids, { return Ok(None) });
 
@@ -414,15 +848,105 @@ fn apply_to_commit2(
             };
             repo.find_tree(tree::subtract(transaction, commit.tree_id(), bf)?)?
         }
+        Op::Rev(filters) => {
+            for (i, (tip, startfilter)) in filters.iter().enumerate() {
+                if *tip == git2::Oid::zero() {
+                    break;
+                }
+                if !is_ancestor_of(repo, commit.id(), *tip)? {
+                    continue;
+                }
+
+                // All ancestors of `commit` are also ancestors of `tip`, so entries after
+                // this one can never be selected for them, while earlier ones still can.
+                let mut remaining = filters[..i].to_vec();
+                remaining.push((git2::Oid::zero(), *startfilter));
+                let op = if remaining.len() == 1 {
+                    to_op_owned(*startfilter)
+                } else {
+                    Op::Rev(remaining)
+                };
+
+                let filtered = some_or!(apply_to_commit2(&op, commit, transaction)?, {
+                    return Ok(None);
+                });
+                transaction.insert(filter, commit.id(), filtered, true)?;
+                return Ok(Some(filtered));
+            }
+
+            apply(transaction, rev_default(filters), commit.tree()?)?
+        }
+        Op::Follow(path) => {
+            let has_dir = |commit: &git2::Commit| -> JoshResult<bool> {
+                Ok(commit
+                    .tree()?
+                    .get_path(path)
+                    .map(|x| x.kind() == Some(git2::ObjectType::Tree))
+                    .unwrap_or(false))
+            };
+
+            // When the directory does not exist in a parent, its history continues
+            // at the location it was moved from.
+            let filtered_parent_ids = commit
+                .parents()
+                .map(|parent| {
+                    let moved_from = if has_dir(commit)? && !has_dir(&parent)? {
+                        find_move(repo, &parent, commit, path)?
+                    } else {
+                        None
+                    };
+                    match moved_from {
+                        Some(from) => apply_to_commit2(&Op::Follow(from), &parent, transaction),
+                        None => transaction.get(filter, parent.id()),
+                    }
+                })
+                .collect::<JoshResult<Option<Vec<_>>>>()?;
+
+            let filtered_parent_ids = some_or!(filtered_parent_ids, { return Ok(None) });
+
+            let filtered_tree = apply(
+                transaction,
+                to_filter(Op::Subdir(path.to_owned())),
+                commit.tree()?,
+            )?;
+
+            return Some(history::create_filtered_commit(
+                commit,
+                filtered_parent_ids,
+                filtered_tree,
+                transaction,
+                filter,
+                history::CommitMeta::default(),
+            ))
+            .transpose();
+        }
+        Op::Since(cutoff) => {
+            let before = match parse_cutoff(cutoff)? {
+                Cutoff::Time(time) => commit.time().seconds() < time,
+                Cutoff::Commit(id) => {
+                    commit.id() != id
+                        && ok_or!(repo.graph_descendant_of(id, commit.id()), {
+                            return Err(josh_error(&format!(
+                                "`:since=...` with nonexistent OID: {}",
+                                id
+                            )));
+                        })
+                }
+            };
+            // Older commits are dropped without looking at their parents, so the first
+            // commit after the cutoff becomes a root with the full tree.
+            if before {
+                transaction.insert(filter, commit.id(), git2::Oid::zero(), true)?;
+                return Ok(Some(git2::Oid::zero()));
+            }
+            commit.tree()?
+        }
         _ => apply(transaction, filter, commit.tree()?)?,
     };
 
     let filtered_parent_ids = {
         rs_tracing::trace_scoped!("filtered_parent_ids", "n": commit.parent_ids().len());
-        commit
-            .parents()
-            .map(|x| transaction.get(filter, x.id()))
-            .collect::<Option<_>>()
+        transaction.get_all(filter, commit.parent_ids())?
     };
 
     let filtered_parent_ids = some_or!(filtered_parent_ids, { return Ok(None) });
@@ -433,23 +957,325 @@ fn apply_to_commit2(
         filtered_tree,
         transaction,
         filter,
+        history::CommitMeta::default(),
     ))
     .transpose()
 }
 
+/// Find the directory in `parent` that `path` in `commit` was moved from, using git's
+/// rename detection. The directory most of the moved files come from is used.
+fn find_move(
+    repo: &git2::Repository,
+    parent: &git2::Commit,
+    commit: &git2::Commit,
+    path: &Path,
+) -> JoshResult<Option<std::path::PathBuf>> {
+    let mut diff = repo.diff_tree_to_tree(Some(&parent.tree()?), Some(&commit.tree()?), None)?;
+    diff.find_similar(Some(git2::DiffFindOptions::new().renames(true)))?;
+
+    let mut counts = std::collections::BTreeMap::<std::path::PathBuf, usize>::new();
+    for delta in diff.deltas() {
+        if delta.status() != git2::Delta::Renamed {
+            continue;
+        }
+        let (old, new) = match (delta.old_file().path(), delta.new_file().path()) {
+            (Some(old), Some(new)) => (old, new),
+            _ => continue,
+        };
+        let relative = ok_or!(new.strip_prefix(path), { continue });
+        if !old.ends_with(relative) {
+            continue;
+        }
+        if let Some(from) = old.ancestors().nth(relative.components().count()) {
+            if from != Path::new("") {
+                *counts.entry(from.to_owned()).or_default() += 1;
+            }
+        }
+    }
+
+    Ok(counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(from, _)| from))
+}
+
+/// A condition on the metadata of a commit, used by `:drop_commits(...)`
+#[derive(Clone, Debug)]
+enum CommitMatch {
+    /// Glob matched against the name or email of the author
+    Author(glob::Pattern),
+    /// Glob matched against the name or email of the committer
+    Committer(glob::Pattern),
+    /// Regex searched for in the commit message
+    Message(regex::Regex),
+}
+
+impl CommitMatch {
+    fn new(field: &str, pattern: &str) -> Result<CommitMatch, String> {
+        let glob = || glob::Pattern::new(pattern).map_err(|e| e.to_string());
+        Ok(match field {
+            "author" => CommitMatch::Author(glob()?),
+            "committer" => CommitMatch::Committer(glob()?),
+            "message" => {
+                CommitMatch::Message(regex::Regex::new(pattern).map_err(|e| e.to_string())?)
+            }
+            _ => return Err(format!("unknown field: {:?}", field)),
+        })
+    }
+
+    fn field(&self) -> &'static str {
+        match self {
+            CommitMatch::Author(_) => "author",
+            CommitMatch::Committer(_) => "committer",
+            CommitMatch::Message(_) => "message",
+        }
+    }
+
+    fn pattern(&self) -> &str {
+        match self {
+            CommitMatch::Author(p) | CommitMatch::Committer(p) => p.as_str(),
+            CommitMatch::Message(r) => r.as_str(),
+        }
+    }
+
+    fn matches(&self, commit: &git2::Commit) -> bool {
+        let signature = |pattern: &glob::Pattern, s: git2::Signature| {
+            s.email().is_some_and(|x| pattern.matches(x))
+                || s.name().is_some_and(|x| pattern.matches(x))
+        };
+        match self {
+            CommitMatch::Author(p) => signature(p, commit.author()),
+            CommitMatch::Committer(p) => signature(p, commit.committer()),
+            CommitMatch::Message(r) => r.is_match(commit.message().unwrap_or("")),
+        }
+    }
+}
+
+/// Where the history of `:since=...` starts
+enum Cutoff {
+    /// Commit time in seconds since the epoch
+    Time(i64),
+    Commit(git2::Oid),
+}
+
+/// Read the argument of `:since=...`, which is either a full commit id or a date
+/// in `YYYY-MM-DD` format (UTC)
+fn parse_cutoff(cutoff: &str) -> JoshResult<Cutoff> {
+    if cutoff.len() == 40 {
+        if let Ok(id) = git2::Oid::from_str(cutoff) {
+            return Ok(Cutoff::Commit(id));
+        }
+    }
+    let invalid = || josh_error(&format!("invalid cutoff: {:?}", cutoff));
+    let date = chrono::NaiveDate::parse_from_str(cutoff, "%Y-%m-%d").map_err(|_| invalid())?;
+    let midnight = date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+    Ok(Cutoff::Time(midnight.timestamp()))
+}
+
+/// The message of a merge commit in a `:linear` history: The original message
+/// followed by the summaries of all commits brought in by the merge.
+fn linear_message(repo: &git2::Repository, commit: &git2::Commit) -> JoshResult<String> {
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(git2::Sort::REVERSE | git2::Sort::TOPOLOGICAL)?;
+    for parent in commit.parent_ids().skip(1) {
+        walk.push(parent)?;
+    }
+    walk.hide(commit.parent_id(0)?)?;
+
+    let mut message = commit
+        .message_raw()
+        .unwrap_or("no message")
+        .trim_end()
+        .to_string();
+    message.push_str("\n\n");
+    for id in walk {
+        let merged = repo.find_commit(id?)?;
+        message.push_str(&format!("* {}\n", merged.summary().unwrap_or("no message")));
+    }
+    Ok(message)
+}
+
+/// Whether the blob `id` is kept by the `:size<...` or `:text_only` filter `op`.
+fn keep_blob(repo: &git2::Repository, op: &Op, id: git2::Oid) -> bool {
+    match op {
+        Op::Size(limit) => repo
+            .odb()
+            .and_then(|odb| odb.read_header(id))
+            .map(|(size, _)| (size as u64) < *limit)
+            .unwrap_or(true),
+        Op::TextOnly => repo.find_blob(id).map(|b| !b.is_binary()).unwrap_or(true),
+        _ => true,
+    }
+}
+
+/// The blobs of `tree` removed by the `:size<...` or `:text_only` filter `op`.
+/// Other entries, like gitlinks, are always part of the view.
+fn excluded_blobs<'a>(
+    transaction: &'a cache::Transaction,
+    op: &Op,
+    tree: &git2::Tree,
+) -> JoshResult<git2::Tree<'a>> {
+    let repo = transaction.repo();
+    tree::remove_pred(
+        transaction,
+        Path::new(""),
+        tree.id(),
+        &|_, blob| blob.is_some_and(|id| !keep_blob(repo, op, id)),
+        to_filter(Op::Exclude(to_filter(op.clone()))).id(),
+    )
+}
+
+/// Render `template` for the message of `commit`, leaving out all trailers matching
+/// one of the `strip` regexes.
+fn rewrite_message(commit: &git2::Commit, template: &str, strip: &[regex::Regex]) -> String {
+    lazy_static! {
+        static ref PLACEHOLDER: regex::Regex =
+            regex::Regex::new(r"\{(summary|body|trailers|original_id)\}")
+                .expect("can't compile regex");
+        static ref TRAILER: regex::Regex =
+            regex::Regex::new(r"^[[:alnum:]-]+: ").expect("can't compile regex");
+    }
+
+    let message = commit.message_raw().unwrap_or("no message").trim();
+
+    // Trailers are the lines of the last paragraph, if all of them look like one.
+    let (message, trailers) = match message.rfind("\n\n") {
+        Some(pos) if message[pos..].trim().lines().all(|l| TRAILER.is_match(l)) => (
+            message[..pos].trim_end(),
+            message[pos..].trim().lines().collect(),
+        ),
+        _ => (message, vec![]),
+    };
+    let (summary, body) = message.split_once("\n\n").unwrap_or((message, ""));
+
+    let render = |line: &str| {
+        PLACEHOLDER
+            .replace_all(line, |caps: &regex::Captures| match &caps[1] {
+                "summary" => summary.lines().collect::<Vec<_>>().join(" "),
+                "body" => body.trim().to_string(),
+                "trailers" => trailers
+                    .iter()
+                    .filter(|t| !strip.iter().any(|regex| regex.is_match(t)))
+                    .map(|t| format!("{}\n", t))
+                    .collect(),
+                _ => commit.id().to_string(),
+            })
+            .into_owned()
+    };
+
+    // Template lines with only empty placeholders are dropped together with the blank
+    // lines around them that would otherwise pile up. Blank lines in the substituted
+    // text and elsewhere in the template are kept.
+    let mut result = String::new();
+    let mut dropped = false;
+    for line in template.lines() {
+        let rendered = render(line);
+        if PLACEHOLDER.is_match(line) && rendered.trim().is_empty() {
+            dropped = true;
+            continue;
+        }
+        let rendered = rendered.strip_suffix('\n').unwrap_or(&rendered);
+        for line in rendered.split('\n').map(str::trim_end) {
+            if line.is_empty() && dropped && (result.is_empty() || result.ends_with("\n\n")) {
+                continue;
+            }
+            dropped &= line.is_empty();
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+    let result = result.trim();
+    if result.is_empty() {
+        return String::new();
+    }
+    format!("{}\n", result)
+}
+
+/// The path of the file in the input tree that `:workspace=...` or `:include=...`
+/// read their filter from.
+fn filter_file(op: &Op) -> std::path::PathBuf {
+    match op {
+        Op::Workspace(path) => path.join("workspace.josh"),
+        Op::Include(path) => path.to_owned(),
+        _ => std::path::PathBuf::new(),
+    }
+}
+
+/// The filter stored at `path` in `tree`. Missing or invalid files result in `:empty`.
+fn included(repo: &git2::Repository, tree: &git2::Tree, path: &Path) -> Filter {
+    parse::parse(&tree::get_blob(repo, tree, path)).unwrap_or(to_filter(Op::Empty))
+}
+
+/// Nesting depth of `:include=...` at which applying a filter is given up on. Includes
+/// can legitimately nest when they read files from different trees, but only so deep.
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// The chain of includes after entering `path` in `tree`. A file that ends up including
+/// itself for the same tree would never finish applying, so that is an error.
+fn include_chain<'p>(
+    includes: &[(git2::Oid, &'p Path)],
+    tree: &git2::Tree,
+    path: &'p Path,
+) -> JoshResult<Vec<(git2::Oid, &'p Path)>> {
+    let mut chain = includes.to_vec();
+    chain.push((tree.id(), path));
+    let paths = || {
+        chain
+            .iter()
+            .map(|(_, path)| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    };
+    if includes.contains(&(tree.id(), path)) {
+        return Err(josh_error(&format!("include cycle: {}", paths())));
+    }
+    if includes.len() >= MAX_INCLUDE_DEPTH {
+        return Err(josh_error(&format!(
+            "includes nested too deeply: {}",
+            paths()
+        )));
+    }
+    Ok(chain)
+}
+
+fn is_ancestor_of(
+    repo: &git2::Repository,
+    ancestor: git2::Oid,
+    descendant: git2::Oid,
+) -> JoshResult<bool> {
+    Ok(ancestor == descendant
+        || ok_or!(repo.graph_descendant_of(descendant, ancestor), {
+            return Err(josh_error(&format!(
+                "`:rev(...)` with nonexistent OID: {}",
+                descendant
+            )));
+        }))
+}
+
+/// The filter used by `:rev(...)` for commits that are not an ancestor of any of
+/// the listed commits. This includes all commits created after the filter was written.
+fn rev_default(filters: &[(git2::Oid, Filter)]) -> Filter {
+    match filters.last() {
+        Some((id, f)) if *id == git2::Oid::zero() => *f,
+        _ => nop(),
+    }
+}
+
 /// Filter a single tree. This does not involve walking history and is thus fast in most cases.
 pub fn apply<'a>(
     transaction: &'a cache::Transaction,
     filter: Filter,
     tree: git2::Tree<'a>,
 ) -> JoshResult<git2::Tree<'a>> {
-    apply2(transaction, &to_op(filter), tree)
+    apply2(transaction, &*to_op_checked(filter)?, tree, &[])
 }
 
 fn apply2<'a>(
     transaction: &'a cache::Transaction,
     op: &Op,
     tree: git2::Tree<'a>,
+    includes: &[(git2::Oid, &Path)],
 ) -> JoshResult<git2::Tree<'a>> {
     let repo = transaction.repo();
     match op {
@@ -457,6 +1283,12 @@ fn apply2<'a>(
         Op::Empty => return Ok(tree::empty(repo)),
         Op::Fold => Ok(tree),
         Op::Squash => Ok(tree),
+        Op::SquashSelect(_) => Ok(tree),
+        Op::Linear => Ok(tree),
+        Op::Since(_) => Ok(tree),
+        Op::DropCommits(_) => Ok(tree),
+        Op::Mailmap(_) => Ok(tree),
+        Op::Message(..) => Ok(tree),
 
         Op::Glob(pattern) => {
             let pattern = glob::Pattern::new(pattern)?;
@@ -467,12 +1299,47 @@ fn apply2<'a>(
             };
             tree::remove_pred(
                 transaction,
-                "",
+                Path::new(""),
                 tree.id(),
-                &|path, isblob| isblob && (pattern.matches_path_with(path, options)),
+                &|path, blob| blob.is_some() && (pattern.matches_path_with(path, options)),
                 to_filter(op.clone()).id(),
             )
         }
+        Op::Patterns(path) => {
+            let (patterns, key) = patterns::read(repo, to_filter(op.clone()), &tree, path)?;
+            Ok(repo.find_tree(patterns::select(
+                transaction,
+                &patterns,
+                Path::new(""),
+                tree.id(),
+                true,
+                key,
+            )?)?)
+        }
+        Op::Owners(owner) => {
+            let (patterns, key) = patterns::read_owners(repo, to_filter(op.clone()), &tree, owner)?;
+            Ok(repo.find_tree(patterns::select(
+                transaction,
+                &patterns,
+                Path::new(""),
+                tree.id(),
+                true,
+                key,
+            )?)?)
+        }
+        Op::Submodules => Ok(repo.find_tree(tree::inline_submodules(
+            transaction,
+            Path::new(""),
+            tree.id(),
+            to_filter(op.clone()).id(),
+        )?)?),
+        Op::Plugin(name, args) => {
+            plugin::apply(transaction, to_filter(op.clone()), name, args, tree)
+        }
+        Op::Size(_) | Op::TextOnly => {
+            let excluded = excluded_blobs(transaction, op, &tree)?;
+            Ok(repo.find_tree(tree::subtract(transaction, tree.id(), excluded.id())?)?)
+        }
         Op::File(path) => {
             let (file, mode) = tree
                 .get_path(path)
@@ -485,7 +1352,7 @@ fn apply2<'a>(
             }
         }
 
-        Op::Subdir(path) => {
+        Op::Subdir(path) | Op::Follow(path) => {
             return Ok(tree
                 .get_path(path)
                 .and_then(|x| repo.find_tree(x.id()))
@@ -494,48 +1361,145 @@ fn apply2<'a>(
         Op::Prefix(path) => tree::insert(repo, &tree::empty(repo), path, tree.id(), 0o0040000),
 
         Op::Subtract(a, b) => {
-            let af = apply(transaction, *a, tree.clone())?;
-            let bf = apply(transaction, *b, tree.clone())?;
-            let bu = unapply(transaction, *b, bf, tree::empty(repo))?;
-            let ba = apply(transaction, *a, bu)?.id();
+            let af = apply2(transaction, &to_op(*a), tree.clone(), includes)?;
+            let bf = apply2(transaction, &to_op(*b), tree.clone(), includes)?;
+            let bu = unapply2(transaction, &to_op(*b), bf, tree::empty(repo), includes)?;
+            let ba = apply2(transaction, &to_op(*a), bu, includes)?.id();
             Ok(repo.find_tree(tree::subtract(transaction, af.id(), ba)?)?)
         }
         Op::Exclude(b) => {
-            let bf = apply(transaction, *b, tree.clone())?.id();
+            let bf = apply2(transaction, &to_op(*b), tree.clone(), includes)?.id();
             Ok(repo.find_tree(tree::subtract(transaction, tree.id(), bf)?)?)
         }
 
-        Op::Paths => tree::pathstree("", tree.id(), transaction),
+        Op::Paths => tree::pathstree(Path::new(""), tree.id(), transaction),
         #[cfg(feature = "search")]
         Op::Index => tree::trigram_index(transaction, tree),
 
-        Op::Invert => tree::invert_paths(transaction, "", tree),
+        Op::Invert => tree::invert_paths(transaction, Path::new(""), tree),
 
         Op::Workspace(path) => {
             let base = to_filter(Op::Subdir(path.to_owned()));
             if let Ok(cw) = parse::parse(&tree::get_blob(repo, &tree, &path.join("workspace.josh")))
             {
-                apply(transaction, compose(base, cw), tree)
+                apply2(transaction, &to_op(compose(base, cw)), tree, includes)
             } else {
-                apply(transaction, base, tree)
+                apply2(transaction, &to_op(base), tree, includes)
             }
         }
 
         Op::Compose(filters) => {
             let filtered: Vec<_> = filters
                 .iter()
-                .map(|f| apply(transaction, *f, tree.clone()))
+                .map(|f| apply2(transaction, &to_op(*f), tree.clone(), includes))
                 .collect::<JoshResult<_>>()?;
             let filtered: Vec<_> = filters.iter().zip(filtered.into_iter()).collect();
             tree::compose(transaction, filtered)
         }
 
         Op::Chain(a, b) => {
-            return apply(transaction, *b, apply(transaction, *a, tree)?);
+            let a = apply2(transaction, &to_op(*a), tree, includes)?;
+            return apply2(transaction, &to_op(*b), a, includes);
+        }
+        Op::Ref(_, f) => apply2(transaction, &to_op(*f), tree, includes),
+        Op::Include(path) => {
+            let includes = include_chain(includes, &tree, path)?;
+            apply2(
+                transaction,
+                &to_op(included(repo, &tree, path)),
+                tree,
+                &includes,
+            )
         }
+        Op::Rev(filters) => apply2(transaction, &to_op(rev_default(filters)), tree, includes),
+        Op::Replace(replacements) => tree::regex_replace(
+            transaction,
+            tree.id(),
+            replacements,
+            to_filter(op.clone()).id(),
+        ),
     }
 }
 
+/// A reason why commits pushed to a view can't be mapped back, see `check_reversible`.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// The sub-filter causing the problem
+    pub filter: Filter,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", spec(self.filter), self.message)
+    }
+}
+
+/// Check if commits pushed to a view created with `filter` can be mapped back using
+/// `unapply` without actually doing it. All sub-filters preventing that are returned.
+/// Problems that depend on the content of the tree are not detected.
+pub fn check_reversible(filter: Filter) -> Result<(), Vec<Diagnostic>> {
+    let mut diagnostics = vec![];
+    check_reversible2(filter, &mut diagnostics);
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+fn check_reversible2(filter: Filter, diagnostics: &mut Vec<Diagnostic>) {
+    let message = match to_op_owned(filter) {
+        Op::Chain(a, b) => {
+            check_reversible2(a, diagnostics);
+            check_reversible2(b, diagnostics);
+            return;
+        }
+        Op::Compose(filters) => {
+            for f in filters {
+                check_reversible2(f, diagnostics);
+            }
+            return;
+        }
+        Op::Exclude(b) => return check_reversible2(b, diagnostics),
+        Op::Ref(_, f) => return check_reversible2(f, diagnostics),
+        Op::Rev(filters) => return check_reversible2(rev_default(&filters), diagnostics),
+
+        Op::Nop
+        | Op::Empty
+        | Op::Linear
+        | Op::Since(_)
+        | Op::TextOnly
+        | Op::Submodules
+        | Op::File(_)
+        | Op::Prefix(_)
+        | Op::Subdir(_)
+        | Op::Follow(_)
+        | Op::Workspace(_)
+        | Op::Include(_)
+        | Op::Mailmap(_)
+        | Op::Patterns(_)
+        | Op::Owners(_)
+        | Op::Glob(_)
+        | Op::Size(_)
+        | Op::Replace(_)
+        | Op::Message(..)
+        | Op::DropCommits(_) => return,
+
+        Op::Subtract(..) | Op::Fold | Op::Squash | Op::SquashSelect(_) | Op::Paths | Op::Invert => {
+            "filter not reversible"
+        }
+        #[cfg(feature = "search")]
+        Op::Index => "filter not reversible",
+        Op::Plugin(..) => "plugin filters are not reversible",
+    };
+
+    diagnostics.push(Diagnostic {
+        filter,
+        message: message.to_string(),
+    });
+}
+
 /// Calculate a tree with minimal differences from `parent_tree`
 /// such that `apply(unapply(tree, parent_tree)) == tree`
 pub fn unapply<'a>(
@@ -544,7 +1508,13 @@ pub fn unapply<'a>(
     tree: git2::Tree<'a>,
     parent_tree: git2::Tree<'a>,
 ) -> JoshResult<git2::Tree<'a>> {
-    unapply2(transaction, &to_op(filter), tree, parent_tree)
+    unapply2(
+        transaction,
+        &*to_op_checked(filter)?,
+        tree,
+        parent_tree,
+        &[],
+    )
 }
 
 fn unapply2<'a>(
@@ -552,15 +1522,27 @@ fn unapply2<'a>(
     op: &Op,
     tree: git2::Tree<'a>,
     parent_tree: git2::Tree<'a>,
+    includes: &[(git2::Oid, &Path)],
 ) -> JoshResult<git2::Tree<'a>> {
     return match op {
         Op::Nop => Ok(tree),
         Op::Empty => Ok(parent_tree),
+        Op::Linear => Ok(tree),
+        Op::Since(_) => Ok(tree),
+        Op::DropCommits(_) => Ok(tree),
+        Op::Mailmap(_) => Ok(tree),
+        Op::Message(..) => Ok(tree),
 
         Op::Chain(a, b) => {
-            let p = apply(transaction, *a, parent_tree.clone())?;
-            let x = unapply(transaction, *b, tree, p)?;
-            unapply(transaction, *a, x, parent_tree)
+            let p = apply2(transaction, &to_op(*a), parent_tree.clone(), includes)?;
+            let x = unapply2(transaction, &to_op(*b), tree, p, includes)?;
+            unapply2(transaction, &to_op(*a), x, parent_tree, includes)
+        }
+        Op::Ref(_, f) => unapply2(transaction, &to_op(*f), tree, parent_tree, includes),
+        Op::Include(path) => {
+            let includes = include_chain(includes, &parent_tree, path)?;
+            let f = included(transaction.repo(), &parent_tree, path);
+            unapply2(transaction, &to_op(f), tree, parent_tree, &includes)
         }
         Op::Workspace(path) => {
             let root = to_filter(Op::Subdir(path.to_owned()));
@@ -595,11 +1577,12 @@ fn unapply2<'a>(
                 0o0100644,
             )?;
 
-            let r = unapply(
+            let r = unapply2(
                 transaction,
-                compose(root, parsed),
+                &to_op(compose(root, parsed)),
                 tree.clone(),
                 parent_tree,
+                includes,
             )?;
 
             // Remove the dummy file inserted above
@@ -631,17 +1614,24 @@ fn unapply2<'a>(
             let mut result = parent_tree.clone();
 
             for other in filters.iter().rev() {
-                let from_empty = unapply(
+                let from_empty = unapply2(
                     transaction,
-                    *other,
+                    &to_op(*other),
                     remaining.clone(),
                     tree::empty(transaction.repo()),
+                    includes,
                 )?;
                 if tree::empty_id() == from_empty.id() {
                     continue;
                 }
-                result = unapply(transaction, *other, remaining.clone(), result)?;
-                let reapply = apply(transaction, *other, from_empty.clone())?;
+                result = unapply2(
+                    transaction,
+                    &to_op(*other),
+                    remaining.clone(),
+                    result,
+                    includes,
+                )?;
+                let reapply = apply2(transaction, &to_op(*other), from_empty.clone(), includes)?;
 
                 remaining = transaction.repo().find_tree(tree::subtract(
                     transaction,
@@ -676,7 +1666,14 @@ fn unapply2<'a>(
             let subtracted = tree::subtract(
                 transaction,
                 tree.id(),
-                unapply(transaction, *b, tree, tree::empty(transaction.repo()))?.id(),
+                unapply2(
+                    transaction,
+                    &to_op(*b),
+                    tree,
+                    tree::empty(transaction.repo()),
+                    includes,
+                )?
+                .id(),
             )?;
             Ok(transaction.repo().find_tree(tree::overlay(
                 transaction.repo(),
@@ -693,9 +1690,9 @@ fn unapply2<'a>(
             };
             let subtracted = tree::remove_pred(
                 transaction,
-                "",
+                Path::new(""),
                 tree.id(),
-                &|path, isblob| isblob && (pattern.matches_path_with(path, options)),
+                &|path, blob| blob.is_some() && (pattern.matches_path_with(path, options)),
                 to_filter(op.clone()).id(),
             )?;
             Ok(transaction.repo().find_tree(tree::overlay(
@@ -704,17 +1701,165 @@ fn unapply2<'a>(
                 subtracted.id(),
             )?)?)
         }
+        Op::Submodules => {
+            tree::uninline_submodules(transaction, tree, &parent_tree, to_filter(op.clone()).id())
+        }
+        Op::Patterns(_) | Op::Owners(_) => {
+            // The patterns of the original tree decide which paths are part of the view
+            let repo = transaction.repo();
+            let (patterns, key) = match op {
+                Op::Owners(owner) => {
+                    patterns::read_owners(repo, to_filter(op.clone()), &parent_tree, owner)?
+                }
+                Op::Patterns(path) => {
+                    patterns::read(repo, to_filter(op.clone()), &parent_tree, path)?
+                }
+                _ => unreachable!(),
+            };
+            let select = |tree: &git2::Tree, keep| {
+                patterns::select(transaction, &patterns, Path::new(""), tree.id(), keep, key)
+            };
+            Ok(repo.find_tree(tree::overlay(
+                repo,
+                select(&parent_tree, false)?,
+                select(&tree, true)?,
+            )?)?)
+        }
+        Op::Size(_) | Op::TextOnly => {
+            let excluded = excluded_blobs(transaction, op, &parent_tree)?;
+            tree::restore(transaction.repo(), tree, &excluded)
+        }
         Op::Prefix(path) => Ok(tree
             .get_path(path)
             .and_then(|x| transaction.repo().find_tree(x.id()))
             .unwrap_or(tree::empty(transaction.repo()))),
-        Op::Subdir(path) => {
+        Op::Subdir(path) | Op::Follow(path) => {
             tree::insert(transaction.repo(), &parent_tree, path, tree.id(), 0o0040000)
         }
+
+        // Pushed commits are new, so they can't be an ancestor of any of the listed commits.
+        Op::Rev(filters) => unapply2(
+            transaction,
+            &to_op(rev_default(filters)),
+            tree,
+            parent_tree,
+            includes,
+        ),
+        Op::Replace(replacements) => tree::regex_unreplace(
+            transaction,
+            Path::new(""),
+            tree,
+            parent_tree,
+            replacements,
+            tree::replace_inverse(replacements).as_deref(),
+        ),
         _ => return Err(josh_error("filter not reversible")),
     };
 }
 
+/// Restore the identities of a commit pushed to a filtered view by undoing the
+/// `:mailmap` filters it went through. `tree` is the unapplied tree of the commit.
+pub fn unapply_signatures(
+    transaction: &cache::Transaction,
+    filter: Filter,
+    tree: git2::Tree,
+    author: &git2::Signature,
+    committer: &git2::Signature,
+) -> JoshResult<(git2::Signature<'static>, git2::Signature<'static>)> {
+    to_op_checked(filter)?;
+    unapply_signatures2(transaction, filter, tree, author, committer, &[])
+}
+
+fn unapply_signatures2(
+    transaction: &cache::Transaction,
+    filter: Filter,
+    tree: git2::Tree,
+    author: &git2::Signature,
+    committer: &git2::Signature,
+    includes: &[(git2::Oid, &Path)],
+) -> JoshResult<(git2::Signature<'static>, git2::Signature<'static>)> {
+    match to_op_owned(filter) {
+        Op::Chain(a, b) => {
+            let (author, committer) = unapply_signatures2(
+                transaction,
+                b,
+                apply2(transaction, &to_op(a), tree.clone(), includes)?,
+                author,
+                committer,
+                includes,
+            )?;
+            unapply_signatures2(transaction, a, tree, &author, &committer, includes)
+        }
+        Op::Ref(_, f) => unapply_signatures2(transaction, f, tree, author, committer, includes),
+        Op::Include(path) => {
+            let includes = include_chain(includes, &tree, &path)?;
+            let f = included(transaction.repo(), &tree, &path);
+            unapply_signatures2(transaction, f, tree, author, committer, &includes)
+        }
+        Op::Mailmap(path) => {
+            let mailmap = reverse_mailmap(&tree::get_blob(transaction.repo(), &tree, &path))?;
+            Ok((
+                mailmap.resolve_signature(author)?,
+                mailmap.resolve_signature(committer)?,
+            ))
+        }
+        _ => Ok((author.to_owned(), committer.to_owned())),
+    }
+}
+
+/// Build a mailmap mapping the identities produced by `mailmap` back to the original ones.
+/// Entries that can't be reversed because they only change the name, or because several
+/// identities map to the same one, are left out.
+fn reverse_mailmap(mailmap: &str) -> JoshResult<git2::Mailmap> {
+    lazy_static! {
+        static ref ENTRY: regex::Regex = regex::Regex::new(
+            r"^\s*(?P<proper_name>[^<#]*?)\s*<(?P<proper_email>[^>]*)>\s*((?P<commit_name>[^<#]*?)\s*<(?P<commit_email>[^>]*)>)?"
+        )
+        .expect("can't compile regex");
+    }
+
+    fn non_empty(x: Option<regex::Match<'_>>) -> Option<&str> {
+        Some(x?.as_str()).filter(|x| !x.is_empty())
+    }
+
+    let mut entries = vec![];
+    for line in mailmap.lines() {
+        let caps = some_or!(ENTRY.captures(line), { continue });
+        let proper_name = non_empty(caps.name("proper_name"));
+        let commit_name = non_empty(caps.name("commit_name"));
+        let (proper_email, commit_email) = match non_empty(caps.name("commit_email")) {
+            Some(commit_email) => (non_empty(caps.name("proper_email")), commit_email),
+            None => (
+                None,
+                some_or!(non_empty(caps.name("proper_email")), { continue }),
+            ),
+        };
+        let proper_email = some_or!(proper_email, { continue });
+
+        // Only match on the name if the original entry did.
+        let replace_name = commit_name.and(proper_name.or(commit_name));
+        entries.push((commit_name, commit_email, replace_name, proper_email));
+    }
+
+    let mut reversed = git2::Mailmap::new()?;
+    for (commit_name, commit_email, replace_name, proper_email) in entries.iter() {
+        let ambiguous = entries
+            .iter()
+            .filter(|(_, _, n, e)| n == replace_name && e.eq_ignore_ascii_case(proper_email))
+            .count()
+            > 1;
+        if !ambiguous {
+            reversed.add_entry(
+                *commit_name,
+                Some(commit_email),
+                *replace_name,
+                proper_email,
+            )?;
+        }
+    }
+    Ok(reversed)
+}
+
 /// Create a filter that is the result of feeding the output of `first` into `second`
 pub fn chain(first: Filter, second: Filter) -> Filter {
     opt::optimize(to_filter(Op::Chain(first, second)))
@@ -725,35 +1870,123 @@ pub fn compose(first: Filter, second: Filter) -> Filter {
     opt::optimize(to_filter(Op::Compose(vec![first, second])))
 }
 
-/// Compute the warnings (filters not matching anything) for the filter applied to the tree
+/// A path of the input tree that is selected differently by two filters, see `diff_selection`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectionChange {
+    /// The path in the input tree
+    pub path: String,
+    /// Where the path ends up using the first filter, if it is selected
+    pub from: Option<String>,
+    /// Where the path ends up using the second filter, if it is selected
+    pub to: Option<String>,
+}
+
+impl std::fmt::Display for SelectionChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) => write!(f, "~ {}: {} -> {}", self.path, from, to),
+            (Some(from), None) => write!(f, "- {} -> {}", self.path, from),
+            (None, to) => write!(
+                f,
+                "+ {} -> {}",
+                self.path,
+                to.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// List the paths of `tree` that are added, removed or mapped to a different location
+/// when using filter `b` instead of `a`, ordered by path.
+pub fn diff_selection(
+    transaction: &cache::Transaction,
+    a: Filter,
+    b: Filter,
+    tree: git2::Tree,
+) -> JoshResult<Vec<SelectionChange>> {
+    to_op_checked(a)?;
+    to_op_checked(b)?;
+    let repo = transaction.repo();
+
+    // Trees indexed by the input path, containing the path in the output
+    let selected = |filter| {
+        let paths = apply(
+            transaction,
+            chain(to_filter(Op::Paths), filter),
+            tree.clone(),
+        )?;
+        tree::invert_paths(transaction, Path::new(""), paths)
+    };
+    let diff = repo.diff_tree_to_tree(Some(&selected(a)?), Some(&selected(b)?), None)?;
+
+    let mapped = |file: git2::DiffFile| -> JoshResult<Option<String>> {
+        if file.id().is_zero() {
+            return Ok(None);
+        }
+        let blob = repo.find_blob(file.id())?;
+        Ok(Some(escape_path(&tree::pathline(blob.content())?)))
+    };
+
+    let mut changes = vec![];
+    for delta in diff.deltas() {
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .ok_or(josh_error("no path"))?;
+        changes.push(SelectionChange {
+            path: escape_path(path),
+            from: mapped(delta.old_file())?,
+            to: mapped(delta.new_file())?,
+        });
+    }
+    Ok(changes)
+}
+
+/// A problem with a filter found by `compute_warnings`
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub message: String,
+    /// Details if the problem is a filter file that can't be parsed
+    pub parse_error: Option<ParseError>,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Compute the warnings (filters not matching anything or filter files that can't be
+/// parsed) for the filter applied to the tree
 pub fn compute_warnings<'a>(
     transaction: &'a cache::Transaction,
     filter: Filter,
     tree: git2::Tree<'a>,
-) -> Vec<String> {
+) -> Vec<Warning> {
     let mut warnings = Vec::new();
     let mut filter = filter;
 
-    if let Op::Workspace(path) = to_op(filter) {
-        let workspace_filter = &tree::get_blob(
-            transaction.repo(),
-            &tree,
-            &path.join(&Path::new("workspace.josh")),
-        );
-        if let Ok(res) = parse(workspace_filter) {
-            filter = res;
-        } else {
-            warnings.push("couldn't parse workspace\n".to_string());
-            return warnings;
+    if let op @ (Op::Workspace(_) | Op::Include(_)) = to_op(filter).as_ref() {
+        let path = filter_file(op);
+        match try_parse(&tree::get_blob(transaction.repo(), &tree, &path)) {
+            Ok(res) => filter = res,
+            Err(e) => {
+                warnings.push(Warning {
+                    message: format!("couldn't parse {}:\n{}", path.display(), e),
+                    parse_error: Some(e),
+                });
+                return warnings;
+            }
         }
     }
 
     let filter = opt::flatten(filter);
-    if let Op::Compose(filters) = to_op(filter) {
+    if let Op::Compose(filters) = to_op(filter).as_ref() {
         for f in filters {
             let tree = transaction.repo().find_tree(tree.id());
             if let Ok(tree) = tree {
-                warnings.append(&mut compute_warnings2(transaction, f, tree));
+                warnings.append(&mut compute_warnings2(transaction, *f, tree));
             }
         }
     } else {
@@ -766,13 +1999,16 @@ fn compute_warnings2<'a>(
     transaction: &'a cache::Transaction,
     filter: Filter,
     tree: git2::Tree<'a>,
-) -> Vec<String> {
+) -> Vec<Warning> {
     let mut warnings = Vec::new();
 
     let tree = apply(transaction, filter, tree);
     if let Ok(tree) = tree {
         if tree.is_empty() {
-            warnings.push(format!("No match for \"{}\"", pretty(filter, 2)));
+            warnings.push(Warning {
+                message: format!("No match for \"{}\"", pretty(filter, 2)),
+                parse_error: None,
+            });
         }
     }
     warnings
@@ -3,22 +3,16 @@
  * All those functions convert filters from one equivalent representation into another.
  */
 
+use super::arena::Memo;
 use super::*;
 
-lazy_static! {
-    static ref OPTIMIZED: std::sync::Mutex<std::collections::HashMap<Filter, Filter>> =
-        std::sync::Mutex::new(std::collections::HashMap::new());
-    static ref SIMPLIFIED: std::sync::Mutex<std::collections::HashMap<Filter, Filter>> =
-        std::sync::Mutex::new(std::collections::HashMap::new());
-}
-
 /*
  * Attempt to create an alternative representation of a filter AST that is most
  * suitable for fast evaluation and cache reuse.
  */
 pub fn optimize(filter: Filter) -> Filter {
-    if let Some(f) = OPTIMIZED.lock().unwrap().get(&filter) {
-        return *f;
+    if let Some(f) = arena::get_memo(filter, Memo::Optimized) {
+        return f;
     }
     let original = filter;
 
@@ -33,26 +27,102 @@ pub fn optimize(filter: Filter) -> Filter {
         }
     };
 
-    OPTIMIZED.lock().unwrap().insert(original, result);
+    arena::set_memo(original, Memo::Optimized, result);
     result
 }
 
+/*
+ * Choose the form of a filter that is cheapest to apply given the results already in the cache.
+ * Besides the form returned by `optimize`, compositions containing all members of a cached
+ * composition are considered with the cached one in place of its members, so new views can
+ * reuse the work done for existing ones. Of equally cheap forms the one returned by
+ * `optimize` is preferred, then the one using the cached filter first in order of names.
+ */
+pub fn optimize_cached(transaction: &cache::Transaction, filter: Filter) -> Filter {
+    let optimized = optimize(unref(filter));
+    // The statistics are only an optimization, so errors reading them are not fatal
+    reuse_cached(transaction, optimized).unwrap_or(optimized)
+}
+
+fn reuse_cached(transaction: &cache::Transaction, optimized: Filter) -> JoshResult<Filter> {
+    let members = if let Op::Compose(members) = to_op(optimized).as_ref() {
+        members.clone()
+    } else {
+        return Ok(optimized);
+    };
+
+    let mut best = (cost(transaction, optimized)?, optimized);
+    for cached in transaction.cached_filters()? {
+        let run = match to_op(cached).as_ref() {
+            Op::Compose(run) if run.len() > 1 && run.len() < members.len() => run.clone(),
+            _ => continue,
+        };
+        if let Some(i) = members.windows(run.len()).position(|x| x == run) {
+            let mut candidate = members[..i].to_vec();
+            candidate.push(cached);
+            candidate.extend_from_slice(&members[i + run.len()..]);
+            let candidate = to_filter(Op::Compose(candidate));
+            let c = cost(transaction, candidate)?;
+            if c < best.0 {
+                best = (c, candidate);
+            }
+        }
+    }
+    Ok(best.1)
+}
+
+/*
+ * Estimate the work needed to apply a filter as the number of filters that need to be
+ * evaluated, counting compositions once per tree they combine. Filters with results in the
+ * cache only need to be evaluated, together with their parts, for the share of lookups that
+ * is expected to miss, according to the statistics of the cache.
+ */
+fn cost(transaction: &cache::Transaction, filter: Filter) -> JoshResult<f64> {
+    let op = to_op(filter);
+    if let Op::Nop | Op::Empty = op.as_ref() {
+        return Ok(0.0);
+    }
+    if let Op::Chain(a, b) = op.as_ref() {
+        return Ok(cost(transaction, *a)? + cost(transaction, *b)?);
+    }
+    if let Op::Ref(_, f) = op.as_ref() {
+        return cost(transaction, *f);
+    }
+    let miss_rate = transaction.stats(filter)?.miss_rate();
+    if miss_rate == 0.0 {
+        return Ok(0.0);
+    }
+    let uncached = match op.as_ref() {
+        Op::Compose(filters) => {
+            let mut c = filters.len() as f64;
+            for f in filters {
+                c += cost(transaction, *f)?;
+            }
+            c
+        }
+        Op::Subtract(a, b) => 1.0 + cost(transaction, *a)? + cost(transaction, *b)?,
+        Op::Exclude(b) => 1.0 + cost(transaction, *b)?,
+        _ => 1.0,
+    };
+    Ok(miss_rate * uncached)
+}
+
 /*
  * Attempt to create an equivalent representation of a filter AST, that has fewer nodes than the
  * input, but still has a similar structure.
  * Useful as a pre-processing step for pretty printing and also during filter optimization.
  */
 pub fn simplify(filter: Filter) -> Filter {
-    if let Some(f) = SIMPLIFIED.lock().unwrap().get(&filter) {
-        return *f;
+    if let Some(f) = arena::get_memo(filter, Memo::Simplified) {
+        return f;
     }
     rs_tracing::trace_scoped!("simplify", "spec": spec2(&to_op(filter)));
     let original = filter;
-    let result = to_filter(match to_op(filter) {
+    let result = to_filter(match to_op_owned(filter) {
         Op::Compose(filters) => {
             let mut out = vec![];
             for f in filters {
-                if let Op::Compose(mut v) = to_op(f) {
+                if let Op::Compose(mut v) = to_op_owned(f) {
                     out.append(&mut v);
                 } else {
                     out.push(f);
@@ -60,11 +130,11 @@ pub fn simplify(filter: Filter) -> Filter {
             }
             Op::Compose(out.drain(..).map(simplify).collect())
         }
-        Op::Chain(a, b) => match (to_op(a), to_op(b)) {
+        Op::Chain(a, b) => match (to_op_owned(a), to_op_owned(b)) {
             (a, Op::Chain(x, y)) => Op::Chain(to_filter(Op::Chain(to_filter(a), x)), y),
             (Op::Prefix(x), Op::Prefix(y)) => Op::Prefix(y.join(x)),
             (Op::Subdir(x), Op::Subdir(y)) => Op::Subdir(x.join(y)),
-            (Op::Chain(x, y), b) => match (to_op(x), to_op(y), b.clone()) {
+            (Op::Chain(x, y), b) => match (to_op_owned(x), to_op_owned(y), b.clone()) {
                 (x, Op::Prefix(p1), Op::Prefix(p2)) => {
                     Op::Chain(simplify(to_filter(x)), to_filter(Op::Prefix(p2.join(p1))))
                 }
@@ -72,11 +142,11 @@ pub fn simplify(filter: Filter) -> Filter {
             },
             (a, b) => Op::Chain(simplify(to_filter(a)), simplify(to_filter(b))),
         },
-        Op::Subtract(a, b) => match (to_op(a), to_op(b)) {
+        Op::Subtract(a, b) => match (to_op_owned(a), to_op_owned(b)) {
             (a, b) => Op::Subtract(simplify(to_filter(a)), simplify(to_filter(b))),
         },
         Op::Exclude(b) => Op::Exclude(simplify(b)),
-        _ => to_op(filter),
+        _ => to_op_owned(filter),
     });
 
     let r = if result == original {
@@ -85,10 +155,34 @@ pub fn simplify(filter: Filter) -> Filter {
         simplify(result)
     };
 
-    SIMPLIFIED.lock().unwrap().insert(original, r);
+    arena::set_memo(original, Memo::Simplified, r);
     r
 }
 
+/*
+ * Replace references to `let` bindings with the bound filters.
+ * The names of bindings only matter for printing, so filters that differ only in them
+ * should share results in the cache. This also lets the optimizer work across bindings.
+ */
+pub fn unref(filter: Filter) -> Filter {
+    if let Some(f) = arena::get_memo(filter, Memo::Unref) {
+        return f;
+    }
+    let result = match to_op_owned(filter) {
+        Op::Ref(_, f) => unref(f),
+        Op::Compose(filters) => to_filter(Op::Compose(filters.into_iter().map(unref).collect())),
+        Op::Rev(filters) => to_filter(Op::Rev(
+            filters.into_iter().map(|(id, f)| (id, unref(f))).collect(),
+        )),
+        Op::Chain(a, b) => to_filter(Op::Chain(unref(a), unref(b))),
+        Op::Subtract(a, b) => to_filter(Op::Subtract(unref(a), unref(b))),
+        Op::Exclude(b) => to_filter(Op::Exclude(unref(b))),
+        _ => filter,
+    };
+    arena::set_memo(filter, Memo::Unref, result);
+    result
+}
+
 /*
  * Remove nesting from a filter.
  * This "flat" representation of the filter is more suitable calculate
@@ -97,11 +191,11 @@ pub fn simplify(filter: Filter) -> Filter {
 pub fn flatten(filter: Filter) -> Filter {
     rs_tracing::trace_scoped!("flatten", "spec": spec(filter));
     let original = filter;
-    let result = to_filter(match to_op(filter) {
+    let result = to_filter(match to_op_owned(filter) {
         Op::Compose(filters) => {
             let mut out = vec![];
             for f in filters {
-                if let Op::Compose(mut v) = to_op(f) {
+                if let Op::Compose(mut v) = to_op_owned(f) {
                     out.append(&mut v);
                 } else {
                     out.push(f);
@@ -109,7 +203,7 @@ pub fn flatten(filter: Filter) -> Filter {
             }
             Op::Compose(out.drain(..).map(flatten).collect())
         }
-        Op::Chain(af, bf) => match (to_op(af), to_op(bf)) {
+        Op::Chain(af, bf) => match (to_op_owned(af), to_op_owned(bf)) {
             (_, Op::Compose(filters)) => {
                 let mut out = vec![];
                 for f in filters {
@@ -126,11 +220,11 @@ pub fn flatten(filter: Filter) -> Filter {
             }
             _ => Op::Chain(flatten(af), flatten(bf)),
         },
-        Op::Subtract(a, b) => match (to_op(a), to_op(b)) {
+        Op::Subtract(a, b) => match (to_op_owned(a), to_op_owned(b)) {
             (a, b) => Op::Subtract(flatten(to_filter(a)), flatten(to_filter(b))),
         },
         Op::Exclude(b) => Op::Exclude(flatten(b)),
-        _ => to_op(filter),
+        _ => to_op_owned(filter),
     });
 
     if result == original {
@@ -148,8 +242,8 @@ fn group(filters: &Vec<Filter>) -> Vec<Vec<Filter>> {
             continue;
         }
 
-        if let Op::Chain(a, _) = to_op(*f) {
-            if let Op::Chain(x, _) = to_op(res[res.len() - 1][0]) {
+        if let Op::Chain(a, _) = to_op_owned(*f) {
+            if let Op::Chain(x, _) = to_op_owned(res[res.len() - 1][0]) {
                 if a == x {
                     let n = res.len();
                     res[n - 1].push(*f);
@@ -187,7 +281,7 @@ fn group(filters: &Vec<Filter>) -> Vec<Vec<Filter>> {
 }
 
 fn last_chain(rest: Filter, filter: Filter) -> (Filter, Filter) {
-    match to_op(filter) {
+    match to_op_owned(filter) {
         Op::Chain(a, b) => last_chain(to_filter(Op::Chain(rest, a)), b),
         _ => (rest, filter),
     }
@@ -214,7 +308,7 @@ fn common_pre(filters: &Vec<Filter>) -> Option<(Filter, Vec<Filter>)> {
     let mut rest = vec![];
     let mut c: Option<Filter> = None;
     for f in filters {
-        if let Op::Chain(a, b) = to_op(*f) {
+        if let Op::Chain(a, b) = to_op_owned(*f) {
             rest.push(b);
             if c == None {
                 c = Some(a);
@@ -267,8 +361,8 @@ fn iterate(filter: Filter) -> Filter {
             log::debug!(
                 "stepop {}:\n{:?}\n->\n{:?}\n",
                 i,
-                to_op(filter),
-                to_op(optimized)
+                to_op_owned(filter),
+                to_op_owned(optimized)
             );
         }
         filter = optimized;
@@ -284,7 +378,7 @@ fn is_prefix(op: Op) -> bool {
 }
 
 fn prefix_of(op: Op) -> Filter {
-    let last = to_op(last_chain(to_filter(Op::Nop), to_filter(op.clone())).1);
+    let last = to_op_owned(last_chain(to_filter(Op::Nop), to_filter(op.clone())).1);
     to_filter(if is_prefix(last.clone()) {
         last
     } else {
@@ -297,12 +391,12 @@ fn prefix_of(op: Op) -> Filter {
  * is returned.
  */
 fn step(filter: Filter) -> Filter {
-    if let Some(f) = OPTIMIZED.lock().unwrap().get(&filter) {
-        return *f;
+    if let Some(f) = arena::get_memo(filter, Memo::Step) {
+        return f;
     }
     rs_tracing::trace_scoped!("step", "spec": spec(filter));
     let original = filter;
-    let result = to_filter(match to_op(filter) {
+    let result = to_filter(match to_op_owned(filter) {
         Op::Subdir(path) => {
             if path.components().count() > 1 {
                 let mut components = path.components();
@@ -328,7 +422,7 @@ fn step(filter: Filter) -> Filter {
             }
         }
         Op::Compose(filters) if filters.is_empty() => Op::Empty,
-        Op::Compose(filters) if filters.len() == 1 => to_op(filters[0]),
+        Op::Compose(filters) if filters.len() == 1 => to_op_owned(filters[0]),
         Op::Compose(mut filters) => {
             filters.dedup();
             filters.retain(|x| *x != to_filter(Op::Empty));
@@ -349,7 +443,7 @@ fn step(filter: Filter) -> Filter {
                 Op::Compose(filters.drain(..).map(step).collect())
             }
         }
-        Op::Chain(a, b) => match (to_op(a), to_op(b)) {
+        Op::Chain(a, b) => match (to_op_owned(a), to_op_owned(b)) {
             (Op::Chain(x, y), b) => Op::Chain(x, to_filter(Op::Chain(y, to_filter(b)))),
             (Op::Prefix(a), Op::Subdir(b)) if a == b => Op::Nop,
             (Op::Prefix(a), Op::Subdir(b)) if a != b => Op::Empty,
@@ -363,7 +457,7 @@ fn step(filter: Filter) -> Filter {
         Op::Exclude(b) if b == to_filter(Op::Empty) => Op::Nop,
         Op::Exclude(b) => Op::Exclude(step(b)),
         Op::Subtract(a, b) if a == b => Op::Empty,
-        Op::Subtract(af, bf) => match (to_op(af), to_op(bf)) {
+        Op::Subtract(af, bf) => match (to_op_owned(af), to_op_owned(bf)) {
             (Op::Empty, _) => Op::Empty,
             (_, Op::Nop) => Op::Empty,
             (a, Op::Empty) => a,
@@ -387,9 +481,9 @@ fn step(filter: Filter) -> Filter {
             }
             (Op::Compose(mut av), _) if av.contains(&bf) => {
                 av.retain(|x| *x != bf);
-                to_op(step(to_filter(Op::Compose(av))))
+                to_op_owned(step(to_filter(Op::Compose(av))))
             }
-            (_, Op::Compose(bv)) if bv.contains(&af) => to_op(step(to_filter(Op::Empty))),
+            (_, Op::Compose(bv)) if bv.contains(&af) => to_op_owned(step(to_filter(Op::Empty))),
             (Op::Compose(mut av), Op::Compose(mut bv)) => {
                 let v = av.clone();
                 av.retain(|x| !bv.contains(x));
@@ -402,9 +496,29 @@ fn step(filter: Filter) -> Filter {
             }
             (a, b) => Op::Subtract(step(to_filter(a)), step(to_filter(b))),
         },
-        _ => to_op(filter),
+        _ => to_op_owned(filter),
     });
 
-    OPTIMIZED.lock().unwrap().insert(original, result);
+    arena::set_memo(original, Memo::Step, result);
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unref_test() {
+        let a = parse("let a = :/x\n\ny = :@a").unwrap();
+        let b = parse("let b = :/x\n\ny = :@b").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(unref(a), unref(b));
+        assert_eq!(unref(a), parse("y = :/x").unwrap());
+        assert_eq!(
+            optimize(unref(
+                parse("let a = :/x\nlet b = :/x\n\n:[:@a,:@b]").unwrap()
+            )),
+            parse(":/x").unwrap()
+        );
+    }
+}
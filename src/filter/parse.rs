@@ -1,80 +1,192 @@
 use super::*;
-use indoc::{formatdoc, indoc};
 
-fn make_op(args: &[&str]) -> JoshResult<Op> {
-    match args {
-        ["nop"] => Ok(Op::Nop),
-        ["empty"] => Ok(Op::Empty),
-        ["prefix", arg] => Ok(Op::Prefix(Path::new(arg).to_owned())),
-        ["workspace", arg] => Ok(Op::Workspace(Path::new(arg).to_owned())),
-        ["prefix"] => Err(josh_error(indoc!(
-            r#"
-            Filter ":prefix" requires an argument.
-
-            Note: use "=" to provide the argument value:
+/// Filters bound with `let name = ...` in a workspace file
+type Bindings = std::collections::HashMap<String, Filter>;
 
-              :prefix=path
+/// Names of all filters, used to suggest corrections for unknown ones
+const FILTER_NAMES: &[&str] = &[
+    "nop",
+    "empty",
+    "prefix",
+    "workspace",
+    "include",
+    "mailmap",
+    "patterns",
+    "owners",
+    "follow",
+    "plugin",
+    "message",
+    "drop_commits",
+    "rev",
+    "replace",
+    "size",
+    "exclude",
+    "subtract",
+    "SQUASH",
+    "squash",
+    "linear",
+    "since",
+    "text_only",
+    "submodules",
+    "PATHS",
+    "INVERT",
+    "FOLD",
+];
 
-            Where `path` is path to be used as a prefix
-            "#
-        ))),
-        ["workspace"] => Err(josh_error(indoc!(
-            r#"
-            Filter ":workspace" requires an argument.
-
-            Note: use "=" to provide the argument value:
-
-              :workspace=path
+fn requires_argument(name: &str, usage: &str, description: &str) -> ParseError {
+    ParseError::new(&format!("Filter \":{}\" requires an argument", name)).suggest(&format!(
+        "use \"=\" to provide the argument value: \"{}\", where {}",
+        usage, description
+    ))
+}
 
-            Where `path` is path to the directory where workspace.josh file is located
-            "#
-        ))),
+fn make_op(args: &[&str]) -> Result<Op, ParseError> {
+    match args {
+        ["nop"] => Ok(Op::Nop),
+        ["empty"] => Ok(Op::Empty),
+        ["prefix", arg] => Ok(Op::Prefix(unescape_path(arg))),
+        ["workspace", arg] => Ok(Op::Workspace(unescape_path(arg))),
+        ["include", arg] => Ok(Op::Include(unescape_path(arg))),
+        ["mailmap", arg] => Ok(Op::Mailmap(unescape_path(arg))),
+        ["patterns", arg] => Ok(Op::Patterns(unescape_path(arg))),
+        ["owners", arg] => Ok(Op::Owners(arg.to_string())),
+        ["follow", arg] => Ok(Op::Follow(unescape_path(arg.trim_start_matches('/')))),
+        ["plugin", name, ..] if !plugin::valid_name(name) => {
+            Err(ParseError::new(&format!("Invalid plugin name: {:?}", name))
+                .suggest("plugin names may only contain letters, digits, \"_\" and \"-\""))
+        }
+        ["plugin", name, args @ ..] => Ok(Op::Plugin(
+            name.to_string(),
+            args.iter().map(|x| x.to_string()).collect(),
+        )),
+        ["prefix"] => Err(requires_argument(
+            "prefix",
+            ":prefix=path",
+            "`path` is path to be used as a prefix",
+        )),
+        ["workspace"] => Err(requires_argument(
+            "workspace",
+            ":workspace=path",
+            "`path` is path to the directory where workspace.josh file is located",
+        )),
+        ["include"] => Err(requires_argument(
+            "include",
+            ":include=path",
+            "`path` is path to a file containing a filter spec",
+        )),
+        ["mailmap"] => Err(requires_argument(
+            "mailmap",
+            ":mailmap=path",
+            "`path` is path to a file in .mailmap format",
+        )),
+        ["patterns"] => Err(requires_argument(
+            "patterns",
+            ":patterns=path",
+            "`path` is path to a file in .gitignore format",
+        )),
+        ["follow"] => Err(requires_argument(
+            "follow",
+            ":follow=/path",
+            "`path` is the current location of the directory to select",
+        )),
+        ["owners"] => Err(requires_argument(
+            "owners",
+            ":owners=@team",
+            "`@team` is an owner as listed in the CODEOWNERS file",
+        )),
+        ["plugin"] => Err(requires_argument(
+            "plugin",
+            ":plugin=name,arg1,arg2",
+            "`name` is the name of the plugin, which runs the executable `josh-plugin-name`",
+        )),
         ["SQUASH"] => Ok(Op::Squash),
+        ["squash"] => Err(requires_argument(
+            "squash",
+            ":squash=refs/tags/*",
+            "`refs/tags/*` selects the commits to keep, using ref patterns or full commit ids",
+        )),
+        ["squash", selectors @ ..] => {
+            for selector in selectors {
+                let is_id = selector.len() == 40 && git2::Oid::from_str(selector).is_ok();
+                if !is_id && !selector.starts_with("refs/") {
+                    return Err(ParseError::new(&format!(
+                        "Invalid squash selector: {:?}",
+                        selector
+                    ))
+                    .suggest("use a full commit id or a ref pattern starting with \"refs/\""));
+                }
+            }
+            Ok(Op::SquashSelect(
+                selectors.iter().map(|x| x.to_string()).collect(),
+            ))
+        }
+        ["linear"] => Ok(Op::Linear),
+        ["since", arg] => match parse_cutoff(arg) {
+            Ok(_) => Ok(Op::Since(arg.to_string())),
+            Err(_) => Err(
+                ParseError::new(&format!("Invalid cutoff for \":since\": {:?}", arg))
+                    .suggest("use a date like \"2015-01-01\" or a full commit id"),
+            ),
+        },
+        ["since"] => Err(requires_argument(
+            "since",
+            ":since=cutoff",
+            "`cutoff` is a date like \"2015-01-01\" or a full commit id",
+        )),
+        ["text_only"] => Ok(Op::TextOnly),
+        ["submodules"] => Ok(Op::Submodules),
+        ["message"] => Err(
+            ParseError::new("Filter \":message\" requires an argument").suggest(
+                "use parentheses to provide the template and the trailers to remove: \
+                 \":message(\"template\",\"regex\",...)\", where `template` can contain \
+                 {summary}, {body}, {trailers} and {original_id}",
+            ),
+        ),
         ["PATHS"] => Ok(Op::Paths),
         #[cfg(feature = "search")]
         ["INDEX"] => Ok(Op::Index),
         ["INVERT"] => Ok(Op::Invert),
         ["FOLD"] => Ok(Op::Fold),
-        _ => Err(josh_error(
-            formatdoc!(
-                r#"
-                Invalid filter: ":{0}"
-
-                Note: use forward slash at the start of the filter if you're
-                trying to select a subdirectory:
-
-                  :/{0}
-                "#,
-                args[0]
-            )
-            .as_str(),
-        )),
+        _ => Err(
+            ParseError::new(&format!("Invalid filter: \":{}\"", args[0]))
+                .suggest_similar(args[0], FILTER_NAMES)
+                .suggest(&format!(
+                    "use forward slash at the start of the filter if you're \
+                     trying to select a subdirectory: \":/{}\"",
+                    args[0]
+                )),
+        ),
     }
 }
 
-fn parse_item(pair: pest::iterators::Pair<Rule>) -> JoshResult<Op> {
+fn parse_item(pair: Pair, bindings: &Bindings) -> Result<Op, ParseError> {
+    let span = pair.clone();
+    parse_item2(pair, bindings).map_err(|e| e.at(&span))
+}
+
+fn parse_item2(pair: Pair, bindings: &Bindings) -> Result<Op, ParseError> {
     match pair.as_rule() {
         Rule::filter => {
             let v: Vec<_> = pair.into_inner().map(|x| x.as_str()).collect();
             make_op(v.as_slice())
         }
         Rule::filter_nop => Ok(Op::Nop),
-        Rule::filter_subdir => Ok(Op::Subdir(
-            Path::new(pair.into_inner().next().unwrap().as_str()).to_owned(),
-        )),
+        Rule::filter_subdir => Ok(Op::Subdir(unescape_path(
+            pair.into_inner().next().unwrap().as_str(),
+        ))),
         Rule::filter_presub => {
             let mut inner = pair.into_inner();
             let arg = inner.next().unwrap().as_str();
             if arg.ends_with('/') {
                 let arg = arg.trim_end_matches('/');
                 Ok(Op::Chain(
-                    to_filter(Op::Subdir(std::path::PathBuf::from(arg))),
+                    to_filter(Op::Subdir(unescape_path(arg))),
                     to_filter(make_op(&["prefix", arg])?),
                 ))
             } else if arg.contains('*') {
                 Ok(Op::Glob(arg.to_string()))
             } else {
-                Ok(Op::File(Path::new(arg).to_owned()))
+                Ok(Op::File(unescape_path(arg)))
             }
         }
         Rule::filter_noarg => {
@@ -82,107 +194,234 @@ fn parse_item(pair: pest::iterators::Pair<Rule>) -> JoshResult<Op> {
             make_op(&[inner.next().unwrap().as_str()])
         }
         Rule::filter_group => {
-            let v: Vec<_> = pair.into_inner().map(|x| x.as_str()).collect();
+            let v: Vec<_> = pair.into_inner().collect();
 
             match v.as_slice() {
-                [args] => Ok(Op::Compose(parse_group(args)?)),
+                [args] => Ok(Op::Compose(parse_group(args.clone(), bindings)?)),
                 [cmd, args] => {
-                    let g = parse_group(args)?;
-                    match *cmd {
+                    let g = parse_group(args.clone(), bindings)?;
+                    match cmd.as_str() {
                         "exclude" => Ok(Op::Exclude(to_filter(Op::Compose(g)))),
                         "subtract" if g.len() == 2 => Ok(Op::Subtract(g[0], g[1])),
-                        _ => Err(josh_error("parse_item: no match")),
+                        "subtract" => Err(ParseError::new(&format!(
+                            "Filter \":subtract\" requires exactly two filters, got {}",
+                            g.len()
+                        ))),
+                        name => Err(ParseError::new(&format!("Invalid filter: \":{}\"", name))
+                            .suggest_similar(name, &["exclude", "subtract"])),
                     }
                 }
-                _ => Err(josh_error("parse_item: no match {:?}")),
+                _ => Err(ParseError::new("Invalid filter group")),
+            }
+        }
+        Rule::filter_rev => {
+            let mut filters = vec![];
+            for entry in pair.into_inner() {
+                let mut inner = entry.clone().into_inner();
+                let id = inner.next().unwrap().as_str();
+                let filter = parse_pair(inner.next().unwrap(), bindings)?;
+                if filters.last().map(|(id, _)| *id) == Some(git2::Oid::zero()) {
+                    return Err(ParseError::new(
+                        "Filter \":rev\": the \"_\" entry has to be the last one",
+                    )
+                    .at(&entry));
+                }
+                let id = if id == "_" {
+                    git2::Oid::zero()
+                } else {
+                    git2::Oid::from_str(id)
+                        .map_err(|e| ParseError::new(&e.to_string()).at(&entry))?
+                };
+                filters.push((id, filter));
+            }
+            Ok(Op::Rev(filters))
+        }
+        Rule::filter_replace => {
+            let mut replacements = vec![];
+            for entry in pair.into_inner() {
+                let mut inner = entry.into_inner();
+                let regex = parse_regex(inner.next().unwrap())?;
+                let replacement = unquote(inner.next().unwrap());
+                replacements.push((regex, replacement));
             }
+            Ok(Op::Replace(replacements))
+        }
+        Rule::filter_ref => {
+            let name = pair.into_inner().next().unwrap().as_str();
+            match bindings.get(name) {
+                Some(filter) => Ok(to_op_owned(*filter)),
+                None => Err(
+                    ParseError::new(&format!("Unknown filter binding: \":@{}\"", name))
+                        .suggest_similar(
+                            name,
+                            &bindings.keys().map(|x| x.as_str()).collect::<Vec<_>>(),
+                        ),
+                ),
+            }
+        }
+        Rule::filter_size => {
+            let size = pair.into_inner().next().unwrap().as_str();
+            let (number, factor) = match size.chars().last() {
+                Some('K') => (&size[..size.len() - 1], 1 << 10),
+                Some('M') => (&size[..size.len() - 1], 1 << 20),
+                Some('G') => (&size[..size.len() - 1], 1 << 30),
+                _ => (size, 1),
+            };
+            let invalid = || ParseError::new(&format!("Filter \":size\": invalid size {:?}", size));
+            let number: u64 = number.parse().map_err(|_| invalid())?;
+            Ok(Op::Size(number.checked_mul(factor).ok_or_else(invalid)?))
+        }
+        Rule::filter_drop => {
+            let mut conditions = vec![];
+            for entry in pair.into_inner() {
+                let span = entry.clone();
+                let mut inner = entry.into_inner();
+                let field = inner.next().unwrap().as_str();
+                let value = inner.next().unwrap();
+                let pattern = match value.as_rule() {
+                    Rule::string => unquote(value),
+                    _ => value.as_str().trim().to_string(),
+                };
+                let condition = CommitMatch::new(field, &pattern).map_err(|e| {
+                    ParseError::new(&format!("Filter \":drop_commits\": {}", e))
+                        .suggest_similar(field, &["author", "committer", "message"])
+                        .at(&span)
+                })?;
+                conditions.push(condition);
+            }
+            Ok(Op::DropCommits(conditions))
+        }
+        Rule::filter_message => {
+            let mut inner = pair.into_inner();
+            let template = unquote(inner.next().unwrap()).replace("\\n", "\n");
+            let strip = inner.map(parse_regex).collect::<Result<_, _>>()?;
+            Ok(Op::Message(template, strip))
+        }
+        _ => Err(ParseError::new("parse_item: no match")),
+    }
+}
+
+/// Undo the escaping done by `quote`. Other escape sequences are kept, so that they can be
+/// used in regexes without escaping the backslash.
+fn unquote(pair: Pair) -> String {
+    let mut result = String::new();
+    let mut chars = pair.into_inner().next().unwrap().as_str().chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(next @ ('\\' | '"'))) => {
+                chars.next();
+                result.push(next);
+            }
+            _ => result.push(c),
         }
-        _ => Err(josh_error("parse_item: no match")),
     }
+    result
+}
+
+fn parse_regex(pair: Pair) -> Result<regex::Regex, ParseError> {
+    let span = pair.clone();
+    regex::Regex::new(&unquote(pair)).map_err(|e| ParseError::new(&e.to_string()).at(&span))
+}
+
+/// Parse the filter spec matched by `pair`, keeping error locations relative to the
+/// input the pair comes from
+fn parse_pair(pair: Pair, bindings: &Bindings) -> Result<Filter, ParseError> {
+    let offset = pair.as_span().start();
+    parse_with(pair.as_str(), bindings).map_err(|e| e.offset(offset))
 }
 
 fn parse_file_entry(
-    pair: pest::iterators::Pair<Rule>,
+    pair: Pair,
     filters: &mut Vec<Filter>,
-) -> JoshResult<()> {
+    bindings: &Bindings,
+) -> Result<(), ParseError> {
     match pair.as_rule() {
         Rule::file_entry => {
             let mut inner = pair.into_inner();
-            let path = inner.next().unwrap().as_str();
-            let filter = inner
-                .next()
-                .map(|x| x.as_str().to_owned())
-                .unwrap_or(format!(":/{}", path));
-            let filter = parse(&filter)?;
-            let filter = chain(filter, to_filter(Op::Prefix(Path::new(path).to_owned())));
+            let path = inner.next().unwrap();
+            let filter = match inner.next() {
+                Some(filter) => parse_pair(filter, bindings)?,
+                None => parse_with(&format!(":/{}", path.as_str()), bindings)
+                    .map_err(|e| ParseError::new(&e.message).at(&path))?,
+            };
+            let path = path.as_str();
+            let filter = chain(filter, to_filter(Op::Prefix(unescape_path(path))));
             filters.push(filter);
             Ok(())
         }
         Rule::filter_spec => {
-            let filter = pair.as_str();
-            filters.push(parse(filter)?);
+            filters.push(parse_pair(pair, bindings)?);
             Ok(())
         }
         Rule::EOI => Ok(()),
-        _ => Err(josh_error(&format!("invalid workspace file {:?}", pair))),
+        _ => Err(ParseError::new("invalid workspace file").at(&pair)),
     }
 }
 
-fn parse_group(filter_spec: &str) -> JoshResult<Vec<Filter>> {
+fn parse_group(pair: Pair, bindings: &Bindings) -> Result<Vec<Filter>, ParseError> {
     rs_tracing::trace_scoped!("parse_group");
+    let offset = pair.as_span().start();
     let mut filters = vec![];
 
-    match Grammar::parse(Rule::compose, filter_spec) {
-        Ok(mut r) => {
-            let r = r.next().unwrap();
-            for pair in r.into_inner() {
-                parse_file_entry(pair, &mut filters)?;
-            }
-
-            Ok(filters)
-        }
-        Err(r) => {
-            return Err(josh_error(&format!(
-                "Invalid workspace:\n----\n{}\n\n{}\n----",
-                r.to_string().replace("␊", ""),
-                filter_spec
-            )));
-        }
+    let mut r = Grammar::parse(Rule::compose, pair.as_str()).map_err(|e| {
+        ParseError::from_pest(e, Rule::compose)
+            .suggest_subdir(pair.as_str())
+            .offset(offset)
+    })?;
+    for pair in r.next().unwrap().into_inner() {
+        parse_file_entry(pair, &mut filters, bindings).map_err(|e| e.offset(offset))?;
     }
+
+    Ok(filters)
 }
 
-fn parse_workspace(filter_spec: &str) -> JoshResult<Vec<Filter>> {
+fn parse_workspace(filter_spec: &str) -> Result<Vec<Filter>, ParseError> {
     rs_tracing::trace_scoped!("parse_workspace");
 
-    match Grammar::parse(Rule::workspace_file, filter_spec) {
-        Ok(mut r) => {
-            let r = r.next().unwrap();
-            for pair in r.into_inner() {
-                match pair.as_rule() {
-                    Rule::compose => {
-                        let filters = parse_group(pair.as_str())?;
-                        return Ok(filters);
-                    }
-                    Rule::workspace_comments => {
-                        continue;
-                    }
-                    _ => return Err(josh_error(&format!("invalid workspace file {:?}", pair))),
-                };
+    let mut r = Grammar::parse(Rule::workspace_file, filter_spec)
+        .map_err(|e| ParseError::from_pest(e, Rule::workspace_file).suggest_subdir(filter_spec))?;
+    let mut bindings = Bindings::new();
+    for pair in r.next().unwrap().into_inner() {
+        match pair.as_rule() {
+            Rule::let_binding => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap();
+                let filter = parse_pair(inner.next().unwrap(), &bindings)?;
+                if bindings.contains_key(name.as_str()) {
+                    return Err(ParseError::new(&format!(
+                        "Filter binding \"{}\" is defined more than once",
+                        name.as_str()
+                    ))
+                    .at(&name));
+                }
+                bindings.insert(
+                    name.as_str().to_string(),
+                    to_filter(Op::Ref(name.as_str().to_string(), filter)),
+                );
             }
-            Err(josh_error(&"invalid workspace file".to_string()))
-        }
-        Err(r) => {
-            return Err(josh_error(&format!(
-                "Invalid workspace:\n----\n{}\n\n{}\n----",
-                r.to_string().replace("␊", ""),
-                filter_spec
-            )));
-        }
+            Rule::compose => {
+                return parse_group(pair, &bindings);
+            }
+            Rule::workspace_comments => {
+                continue;
+            }
+            _ => return Err(ParseError::new("invalid workspace file").at(&pair)),
+        };
     }
+    Err(ParseError::new("invalid workspace file"))
 }
 
 /// Create a `Filter` from a string representation
 pub fn parse(filter_spec: &str) -> JoshResult<Filter> {
+    try_parse(filter_spec).map_err(|e| josh_error(&e.to_string()))
+}
+
+/// Like `parse`, but returns a `ParseError` pointing at the invalid part of `filter_spec`
+pub fn try_parse(filter_spec: &str) -> Result<Filter, ParseError> {
+    parse_with(filter_spec, &Bindings::new()).map_err(|e| e.locate(filter_spec))
+}
+
+fn parse_with(filter_spec: &str, bindings: &Bindings) -> Result<Filter, ParseError> {
     if filter_spec.is_empty() {
         return Ok(to_filter(Op::Empty));
     }
@@ -191,7 +430,7 @@ pub fn parse(filter_spec: &str) -> JoshResult<Filter> {
         let mut r = r;
         let r = r.next().unwrap();
         for pair in r.into_inner() {
-            let v = parse_item(pair)?;
+            let v = parse_item(pair, bindings)?;
             chain = Some(if let Some(c) = chain {
                 Op::Chain(to_filter(c), to_filter(v))
             } else {
@@ -229,6 +468,245 @@ pub fn get_comments(filter_spec: &str) -> JoshResult<String> {
     )));
 }
 
+/// Error found while parsing a filter spec, see `try_parse`.
+/// Its `Display` implementation renders the message with an excerpt of the spec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    /// Byte range of the invalid part of the spec
+    pub span: std::ops::Range<usize>,
+    /// Line and column (both starting at 1) of the start of `span`
+    pub line: usize,
+    pub column: usize,
+    pub suggestions: Vec<String>,
+    rule: Option<Rule>,
+    /// The line of the spec containing the start of `span`
+    source_line: String,
+    located: bool,
+}
+
+impl ParseError {
+    fn new(message: &str) -> Self {
+        ParseError {
+            message: message.to_string(),
+            span: 0..0,
+            line: 1,
+            column: 1,
+            rule: None,
+            suggestions: vec![],
+            source_line: String::new(),
+            located: false,
+        }
+    }
+
+    fn from_pest(error: pest::error::Error<Rule>, rule: Rule) -> Self {
+        let message = match error.variant {
+            pest::error::ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => match (enumerate(&positives), enumerate(&negatives)) {
+                (Some(positives), None) => format!("expected {}", positives),
+                (None, Some(negatives)) => format!("unexpected {}", negatives),
+                (Some(positives), Some(negatives)) => {
+                    format!("unexpected {}; expected {}", negatives, positives)
+                }
+                (None, None) => "unexpected input".to_string(),
+            },
+            pest::error::ErrorVariant::CustomError { message } => message,
+        };
+        let mut result = ParseError::new(&message);
+        result.span = match error.location {
+            pest::error::InputLocation::Pos(pos) => pos..pos,
+            pest::error::InputLocation::Span((start, end)) => start..end,
+        };
+        result.rule = Some(rule);
+        result.located = true;
+        result
+    }
+
+    /// The name of the grammar rule that was being parsed
+    pub fn rule(&self) -> Option<String> {
+        self.rule.map(|rule| format!("{:?}", rule))
+    }
+
+    fn suggest(mut self, suggestion: &str) -> Self {
+        self.suggestions.push(suggestion.to_string());
+        self
+    }
+
+    /// Suggest the entries of `candidates` that are close to the misspelled `name`
+    fn suggest_similar(mut self, name: &str, candidates: &[&str]) -> Self {
+        for candidate in candidates {
+            let distance = edit_distance(name, candidate);
+            if distance < name.len() && distance <= std::cmp::max(1, name.len() / 3) {
+                self = self.suggest(&format!("did you mean \"{}\"?", candidate));
+            }
+        }
+        self
+    }
+
+    /// Suggest selecting a subdirectory if the error points at something like ":a/b"
+    fn suggest_subdir(self, input: &str) -> Self {
+        let start = match input[..self.span.start].rfind(':') {
+            Some(start) => start + 1,
+            None => return self,
+        };
+        let path: String = input[start..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || "_-+./".contains(*c))
+            .collect();
+        if path.contains('/') && !path.starts_with('/') && start + path.len() > self.span.start {
+            let suggestion = format!(
+                "use forward slash at the start of the filter if you're \
+                 trying to select a subdirectory: \":/{}\"",
+                path
+            );
+            return self.suggest(&suggestion);
+        }
+        self
+    }
+
+    /// Point the error at `pair`, unless it already has a location
+    fn at(mut self, pair: &Pair) -> Self {
+        if !self.located {
+            self.span = pair.as_span().start()..pair.as_span().end();
+            self.rule = Some(pair.as_rule());
+            self.located = true;
+        }
+        self
+    }
+
+    /// Move the location of the error to account for `offset` bytes of input in front of
+    /// the parsed part of the spec
+    fn offset(mut self, offset: usize) -> Self {
+        if self.located {
+            self.span = self.span.start + offset..self.span.end + offset;
+        }
+        self
+    }
+
+    /// Compute the line and column in `filter_spec`
+    fn locate(mut self, filter_spec: &str) -> Self {
+        if !self.located {
+            self.span = 0..filter_spec.len();
+        }
+        let before = &filter_spec[..self.span.start];
+        let line_start = before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+        let line_end = filter_spec[line_start..]
+            .find('\n')
+            .map(|x| x + line_start)
+            .unwrap_or(filter_spec.len());
+        self.line = before.matches('\n').count() + 1;
+        self.column = before[line_start..].chars().count() + 1;
+        self.source_line = filter_spec[line_start..line_end].to_string();
+        self.located = true;
+        self
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+        let padding: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = std::cmp::max(
+            1,
+            self.source_line
+                .chars()
+                .skip(self.column - 1)
+                .take(self.span.len())
+                .count(),
+        );
+
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "{}--> {}:{}", gutter, self.line, self.column)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(f, "{} | {}{}", gutter, padding, "^".repeat(width))?;
+        if !self.suggestions.is_empty() {
+            write!(f, "\n{} |", gutter)?;
+        }
+        for suggestion in &self.suggestions {
+            write!(f, "\n{} = help: {}", gutter, suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Describe what a grammar rule matches in words a user of the filter syntax
+/// understands. Returns `None` for rules that should not be mentioned.
+fn describe(rule: Rule) -> Option<&'static str> {
+    Some(match rule {
+        Rule::EOI => return None,
+        Rule::filter_spec
+        | Rule::filter_group
+        | Rule::filter_subdir
+        | Rule::filter_nop
+        | Rule::filter_presub
+        | Rule::filter
+        | Rule::filter_rev
+        | Rule::filter_replace
+        | Rule::filter_message
+        | Rule::filter_drop
+        | Rule::filter_size
+        | Rule::filter_ref
+        | Rule::filter_noarg => "a filter such as `:/path`",
+        Rule::cmd => "a filter name",
+        Rule::argument => "an argument",
+        Rule::string | Rule::inner_string => "a quoted string",
+        Rule::size => "a size such as `100K`",
+        Rule::rev_id | Rule::rev_entry => "a commit id or `_`",
+        Rule::replace_entry => "a replacement such as `\"regex\":\"replacement\"`",
+        Rule::drop_entry | Rule::drop_field => "a condition such as `author=name`",
+        Rule::drop_value => "a value",
+        Rule::binding_name => "a name",
+        Rule::let_binding => "a `let` binding",
+        Rule::file_entry | Rule::dst_path | Rule::path => "a path",
+        Rule::compose | Rule::workspace_file => "a list of filters",
+        Rule::workspace_comments => "a comment",
+        _ => return None,
+    })
+}
+
+fn enumerate(rules: &[Rule]) -> Option<String> {
+    let mut names: Vec<&str> = vec![];
+    for name in rules.iter().copied().filter_map(describe) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    match names.as_slice() {
+        [] => None,
+        [a] => Some(a.to_string()),
+        [init @ .., last] => Some(format!("{} or {}", init.join(", "), last)),
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == b[j] {
+                previous
+            } else {
+                1 + std::cmp::min(previous, std::cmp::min(row[j], row[j + 1]))
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+type Pair<'a> = pest::iterators::Pair<'a, Rule>;
+
 #[derive(Parser)]
 #[grammar = "filter/grammar.pest"]
 struct Grammar;
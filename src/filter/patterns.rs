@@ -0,0 +1,256 @@
+use super::*;
+
+const OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+struct Rule {
+    pattern: glob::Pattern,
+    negated: bool,
+    dir_only: bool,
+    /// Match against the full path instead of only the last component
+    anchored: bool,
+    /// Select everything inside matching directories, for nested patterns. Patterns with a
+    /// wildcard in the last component, like `docs/*`, only select the matching files.
+    recursive: bool,
+}
+
+/// A list of patterns in gitignore or CODEOWNERS syntax. Patterns containing a `/` are
+/// relative to the root of the tree.
+pub struct Patterns {
+    rules: Vec<Rule>,
+    /// Rules matching a directory apply to everything inside and can be overridden by later
+    /// rules (CODEOWNERS). Otherwise a selected directory is selected as a whole (gitignore).
+    nested: bool,
+}
+
+/// Locations of the CODEOWNERS file, in the order they are looked up
+const CODEOWNERS: &[&str] = &[".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Translate backslash escapes to the glob syntax
+fn unescape(pattern: &str) -> String {
+    let mut result = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ ('*' | '?' | '[' | ']')) => result.push_str(&format!("[{}]", c)),
+                Some(c) => result.push(c),
+                None => {}
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Whether `pattern` contains wildcards that are not escaped
+fn has_wildcard(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    let line = line.trim_end_matches('\r');
+    if line.starts_with('#') {
+        return None;
+    }
+    let line = if line.ends_with("\\ ") {
+        format!("{} ", line.trim_end_matches("\\ "))
+    } else {
+        line.trim_end_matches(' ').to_string()
+    };
+
+    match line.strip_prefix('!') {
+        Some(rest) => make_rule(rest, true),
+        None => make_rule(&line, false),
+    }
+}
+
+fn make_rule(pattern: &str, negated: bool) -> Option<Rule> {
+    let (dir_only, line) = match pattern.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    if line.is_empty() {
+        return None;
+    }
+    let anchored = line.contains('/');
+    let recursive = dir_only || !has_wildcard(line.rsplit('/').next().unwrap_or_default());
+    let pattern = glob::Pattern::new(&unescape(line.trim_start_matches('/'))).ok()?;
+
+    Some(Rule {
+        pattern,
+        negated,
+        dir_only,
+        anchored,
+        recursive,
+    })
+}
+
+impl Patterns {
+    /// Invalid patterns are ignored, like git does
+    pub fn parse(text: &str) -> Patterns {
+        Patterns {
+            rules: text.lines().filter_map(parse_rule).collect(),
+            nested: false,
+        }
+    }
+
+    /// Select the paths owned by `owner` according to a CODEOWNERS file. Owners are
+    /// compared case insensitively, and the last line matching a path decides its owners.
+    pub fn codeowners(text: &str, owner: &str) -> Patterns {
+        let rules = text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let pattern = fields.next().filter(|x| !x.starts_with('#'))?;
+                let owned = fields
+                    .take_while(|x| !x.starts_with('#'))
+                    .any(|x| x.eq_ignore_ascii_case(owner));
+                make_rule(pattern, !owned)
+            })
+            .collect();
+        Patterns {
+            rules,
+            nested: true,
+        }
+    }
+
+    /// Check if the last rule matching `path` selects it. Parent directories of `path`
+    /// are only considered for nested patterns, see `Rule::recursive`.
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        let mut selected = false;
+        for rule in self.rules.iter() {
+            let matched = rule.matches(path, is_dir)
+                || (self.nested
+                    && rule.recursive
+                    && path
+                        .ancestors()
+                        .skip(1)
+                        .filter(|x| !x.as_os_str().is_empty())
+                        .any(|x| rule.matches(x, true)));
+            if matched {
+                selected = !rule.negated;
+            }
+        }
+        selected
+    }
+}
+
+impl Rule {
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let target = if self.anchored {
+            path
+        } else {
+            Path::new(path.file_name().unwrap_or_default())
+        };
+        self.pattern.matches_path_with(target, OPTIONS)
+    }
+}
+
+fn cache_key(filter: Filter, blob: git2::Oid) -> JoshResult<git2::Oid> {
+    Ok(git2::Oid::hash_object(
+        git2::ObjectType::Blob,
+        format!("{}:{}", filter.id(), blob).as_bytes(),
+    )?)
+}
+
+/// Read the patterns stored at `path` in `tree`. The returned key identifies the result of
+/// filter `filter` given this file and is used for caching.
+pub fn read(
+    repo: &git2::Repository,
+    filter: Filter,
+    tree: &git2::Tree,
+    path: &Path,
+) -> JoshResult<(Patterns, git2::Oid)> {
+    let blob = tree
+        .get_path(path)
+        .map(|x| x.id())
+        .unwrap_or(git2::Oid::zero());
+    let key = cache_key(filter, blob)?;
+    Ok((Patterns::parse(&tree::get_blob(repo, tree, path)), key))
+}
+
+/// Read the paths owned by `owner` from the CODEOWNERS file in `tree`. Like `read`,
+/// also returns a key for caching.
+pub fn read_owners(
+    repo: &git2::Repository,
+    filter: Filter,
+    tree: &git2::Tree,
+    owner: &str,
+) -> JoshResult<(Patterns, git2::Oid)> {
+    let path = CODEOWNERS
+        .iter()
+        .map(Path::new)
+        .find(|path| tree.get_path(path).is_ok())
+        .unwrap_or_else(|| Path::new(CODEOWNERS[0]));
+    let (_, key) = read(repo, filter, tree, path)?;
+    Ok((
+        Patterns::codeowners(&tree::get_blob(repo, tree, path), owner),
+        key,
+    ))
+}
+
+/// Keep only the paths of `input` selected by `patterns`, or if `keep` is false,
+/// remove them. Unless the patterns are nested, directories that are selected are kept
+/// or removed as a whole.
+pub fn select(
+    transaction: &cache::Transaction,
+    patterns: &Patterns,
+    root: &Path,
+    input: git2::Oid,
+    keep: bool,
+    key: git2::Oid,
+) -> JoshResult<git2::Oid> {
+    // Anchored patterns depend on the location of the tree
+    let cache_key = git2::Oid::hash_object(
+        git2::ObjectType::Blob,
+        format!("{}:{}:{}", key, keep, escape_path(root)).as_bytes(),
+    )?;
+    if let Some(cached) = transaction.get_glob((input, cache_key))? {
+        return Ok(cached);
+    }
+
+    let repo = transaction.repo();
+    let tree = repo.find_tree(input)?;
+    let mut builder = repo.treebuilder(None)?;
+
+    for entry in tree.iter() {
+        let name = tree::entry_name(&entry);
+        let path = root.join(name);
+
+        let id = if entry.kind() == Some(git2::ObjectType::Tree) {
+            if !patterns.nested && patterns.matches(&path, true) {
+                Some(entry.id()).filter(|_| keep)
+            } else {
+                Some(select(transaction, patterns, &path, entry.id(), keep, key)?)
+                    .filter(|id| *id != tree::empty_id())
+            }
+        } else {
+            Some(entry.id()).filter(|_| patterns.matches(&path, false) == keep)
+        };
+
+        if let Some(id) = id {
+            builder.insert(name, id, entry.filemode())?;
+        }
+    }
+
+    let result = builder.write()?;
+    transaction.insert_glob((input, cache_key), result)?;
+    Ok(result)
+}
@@ -0,0 +1,269 @@
+use super::*;
+use std::io::{BufRead, Write};
+use std::os::unix::ffi::OsStrExt;
+
+type Stamp = (std::time::SystemTime, u64);
+
+lazy_static! {
+    /// Versions of plugin executables, by path. The modification time and size of the
+    /// executable are remembered to notice when it is replaced.
+    static ref VERSIONS: std::sync::Mutex<std::collections::HashMap<std::path::PathBuf, (Stamp, String)>> =
+        std::sync::Mutex::new(std::collections::HashMap::new());
+
+    /// Names of the plugins that may be run
+    static ref ALLOWED: std::sync::RwLock<std::collections::HashSet<String>> =
+        std::sync::RwLock::new(std::collections::HashSet::new());
+
+    /// Time after which plugins that did not finish are killed
+    static ref TIMEOUT: std::sync::RwLock<std::time::Duration> =
+        std::sync::RwLock::new(std::time::Duration::from_secs(600));
+}
+
+/// Only allow running the plugins in `names`. By default no plugins are allowed, as they
+/// run with the permissions of the process.
+pub fn allow_plugins(names: &[String]) {
+    *ALLOWED.write().unwrap() = names.iter().cloned().collect();
+}
+
+/// Kill plugins that run for longer than `timeout`, 10 minutes by default
+pub fn set_plugin_timeout(timeout: std::time::Duration) {
+    *TIMEOUT.write().unwrap() = timeout;
+}
+
+/// The name of a plugin is part of the name of its executable, so only letters, digits,
+/// `_` and `-` are allowed.
+pub(super) fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Find `josh-plugin-<name>` on the `PATH`
+fn executable(name: &str) -> JoshResult<std::path::PathBuf> {
+    if !valid_name(name) {
+        return Err(josh_error(&format!("invalid plugin name: {:?}", name)));
+    }
+    if !ALLOWED.read()?.contains(name) {
+        return Err(josh_error(&format!("plugin {:?} is not allowed", name)));
+    }
+    let file = format!("josh-plugin-{}", name);
+    std::env::var_os("PATH")
+        .iter()
+        .flat_map(std::env::split_paths)
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+        .ok_or_else(|| josh_error(&format!("can't run plugin {:?}: {} not found", name, file)))
+}
+
+/// The version reported by `josh-plugin-<name> --version`. It is part of the cache key, so
+/// plugins have to change it whenever their output changes.
+fn version(name: &str, executable: &Path) -> JoshResult<String> {
+    let metadata = std::fs::metadata(executable)?;
+    let stamp = (metadata.modified()?, metadata.len());
+    if let Some((known, version)) = VERSIONS.lock()?.get(executable) {
+        if *known == stamp {
+            return Ok(version.clone());
+        }
+    }
+
+    let output = std::process::Command::new(executable)
+        .arg("--version")
+        .output()
+        .map_err(|e| josh_error(&format!("can't run plugin {:?}: {}", name, e)))?;
+
+    if !output.status.success() {
+        return Err(josh_error(&format!(
+            "plugin {:?}: --version failed: {}",
+            name, output.status
+        )));
+    }
+
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    VERSIONS
+        .lock()?
+        .insert(executable.to_owned(), (stamp, version.clone()));
+    Ok(version)
+}
+
+fn collect_blobs(
+    repo: &git2::Repository,
+    tree: &git2::Tree,
+    root: &Path,
+    blobs: &mut Vec<(std::path::PathBuf, i32, Vec<u8>)>,
+) -> JoshResult<()> {
+    for entry in tree.iter() {
+        let path = root.join(tree::entry_name(&entry));
+        match entry.kind() {
+            Some(git2::ObjectType::Blob) => {
+                let content = repo.find_blob(entry.id())?.content().to_vec();
+                blobs.push((path, entry.filemode(), content));
+            }
+            Some(git2::ObjectType::Tree) => {
+                collect_blobs(repo, &repo.find_tree(entry.id())?, &path, blobs)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn write_records(
+    out: &mut impl Write,
+    tree: git2::Oid,
+    blobs: &[(std::path::PathBuf, i32, Vec<u8>)],
+) -> std::io::Result<()> {
+    writeln!(out, "tree {}", tree)?;
+    for (path, mode, content) in blobs {
+        write!(out, "blob {:o} {} ", mode, content.len())?;
+        out.write_all(path.as_os_str().as_bytes())?;
+        writeln!(out)?;
+        out.write_all(content)?;
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+/// Plugins may only output regular files, executables and symlinks, at relative paths
+/// without `.` or `..` components
+fn valid_record(mode: i32, path: &[u8]) -> bool {
+    [0o100644, 0o100755, 0o120000].contains(&mode)
+        && path
+            .split(|x| *x == b'/')
+            .all(|x| !x.is_empty() && x != b"." && x != b"..")
+}
+
+fn read_record(input: &mut impl BufRead) -> JoshResult<Option<(std::path::PathBuf, i32, Vec<u8>)>> {
+    let mut header = vec![];
+    if input.read_until(b'\n', &mut header)? == 0 {
+        return Ok(None);
+    }
+
+    let invalid = || {
+        josh_error(&format!(
+            "invalid record: {:?}",
+            String::from_utf8_lossy(&header)
+        ))
+    };
+    let mut fields = header
+        .strip_suffix(b"\n")
+        .unwrap_or(&header)
+        .splitn(4, |x| *x == b' ');
+    if fields.next() != Some(b"blob") {
+        return Err(invalid());
+    }
+    let mut number = |radix| {
+        let field =
+            std::str::from_utf8(fields.next().ok_or_else(invalid)?).map_err(|_| invalid())?;
+        usize::from_str_radix(field, radix).map_err(|_| invalid())
+    };
+    let mode = number(8)? as i32;
+    let size = number(10)?;
+    let path = fields.next().ok_or_else(invalid)?;
+    if !valid_record(mode, path) {
+        return Err(invalid());
+    }
+    let path = std::path::PathBuf::from(std::ffi::OsStr::from_bytes(path));
+
+    let mut content = vec![0; size + 1];
+    input.read_exact(&mut content)?;
+    if content.pop() != Some(b'\n') {
+        return Err(invalid());
+    }
+
+    Ok(Some((path, mode, content)))
+}
+
+/// Wait for `child` to exit, killing it if it takes longer than `TIMEOUT`
+fn wait(name: &str, child: &mut std::process::Child) -> JoshResult<std::process::ExitStatus> {
+    let timeout = *TIMEOUT.read()?;
+    let start = std::time::Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if start.elapsed() > timeout {
+            child.kill()?;
+            child.wait()?;
+            return Err(josh_error(&format!(
+                "plugin {:?} did not finish within {}s",
+                name,
+                timeout.as_secs()
+            )));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
+
+/// Run `tree` through the plugin `name`. All blobs of the tree are written to the stdin of
+/// `josh-plugin-<name> <args>`, which has to write the blobs of the result to stdout.
+pub fn apply<'a>(
+    transaction: &'a cache::Transaction,
+    filter: Filter,
+    name: &str,
+    args: &[String],
+    tree: git2::Tree<'a>,
+) -> JoshResult<git2::Tree<'a>> {
+    let repo = transaction.repo();
+    let executable = executable(name)?;
+    let key = (spec(filter), version(name, &executable)?, tree.id());
+    if let Some(cached) = transaction.get_plugin(key.clone())? {
+        return Ok(repo.find_tree(cached)?);
+    }
+    rs_tracing::trace_scoped!("plugin", "name": name);
+
+    let mut blobs = vec![];
+    collect_blobs(repo, &tree, Path::new(""), &mut blobs)?;
+
+    let mut child = std::process::Command::new(&executable)
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| josh_error(&format!("can't run plugin {:?}: {}", name, e)))?;
+
+    // Write and read from separate threads, so neither side blocks on a full pipe, and the
+    // plugin can be killed when it does not finish in time.
+    let mut stdin = child.stdin.take().ok_or(josh_error("no stdin"))?;
+    let tree_id = tree.id();
+    let writer = std::thread::spawn(move || write_records(&mut stdin, tree_id, &blobs));
+
+    let stdout = child.stdout.take().ok_or(josh_error("no stdout"))?;
+    let reader = std::thread::spawn(move || {
+        let mut stdout = std::io::BufReader::new(stdout);
+        let mut records = vec![];
+        // On errors the rest of the output is not read, so the plugin does not block
+        while let Some(record) = read_record(&mut stdout)? {
+            records.push(record);
+        }
+        Ok(records)
+    });
+
+    let status = wait(name, &mut child)?;
+    let records: JoshResult<_> = reader
+        .join()
+        .map_err(|_| josh_error(&format!("plugin {:?}: reading the output failed", name)))?;
+    let written = writer
+        .join()
+        .map_err(|_| josh_error(&format!("plugin {:?}: writing the input failed", name)))?;
+    if !status.success() {
+        return Err(josh_error(&format!("plugin {:?} failed: {}", name, status)));
+    }
+    let records =
+        records.map_err(|JoshError(msg)| josh_error(&format!("plugin {:?}: {}", name, msg)))?;
+    // Otherwise the output could be based on part of the input only
+    written.map_err(|e| {
+        josh_error(&format!(
+            "plugin {:?} did not read all of its input: {}",
+            name, e
+        ))
+    })?;
+
+    let mut result = tree::empty(repo);
+    for (path, mode, content) in records {
+        result = tree::insert(repo, &result, &path, repo.blob(&content)?, mode)?;
+    }
+
+    transaction.insert_plugin(key, result.id())?;
+    Ok(result)
+}
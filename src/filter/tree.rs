@@ -2,14 +2,47 @@ use super::*;
 
 #[cfg(feature = "search")]
 use rayon::prelude::*;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+/// The name of a tree entry. Unlike `git2::TreeEntry::name`, this also works for names
+/// that are not valid UTF-8.
+pub fn entry_name<'a>(entry: &'a git2::TreeEntry) -> &'a Path {
+    Path::new(std::ffi::OsStr::from_bytes(entry.name_bytes()))
+}
+
+/// Look up the entry called `name` in `tree`
+fn get_name<'a>(tree: &'a git2::Tree, name: &Path) -> Option<git2::TreeEntry<'a>> {
+    match name.to_str() {
+        Some(name) => tree.get_name(name),
+        None => tree.get_path(name).ok(),
+    }
+}
+
+/// Call `f` with the full path of every entry in `tree`, including those in subtrees.
+/// Unlike `git2::Tree::walk`, this also works for paths that are not valid UTF-8.
+pub fn walk(
+    repo: &git2::Repository,
+    root: &Path,
+    tree: &git2::Tree,
+    f: &mut dyn FnMut(&Path, &git2::TreeEntry),
+) -> super::JoshResult<()> {
+    for entry in tree.iter() {
+        let path = root.join(entry_name(&entry));
+        f(&path, &entry);
+        if entry.kind() == Some(git2::ObjectType::Tree) {
+            walk(repo, &path, &repo.find_tree(entry.id())?, f)?;
+        }
+    }
+    Ok(())
+}
 
 pub fn pathstree<'a>(
-    root: &str,
+    root: &Path,
     input: git2::Oid,
     transaction: &'a cache::Transaction,
 ) -> super::JoshResult<git2::Tree<'a>> {
     let repo = transaction.repo();
-    if let Some(cached) = transaction.get_paths((input, root.to_string())) {
+    if let Some(cached) = transaction.get_paths((input, root.to_owned()))? {
         return Ok(repo.find_tree(cached)?);
     }
 
@@ -17,108 +50,403 @@ pub fn pathstree<'a>(
     let mut result = tree::empty(repo);
 
     for entry in tree.iter() {
-        let name = entry.name().ok_or(super::josh_error("no name"))?;
+        let name = entry_name(&entry);
         if entry.kind() == Some(git2::ObjectType::Blob) {
-            let file_contents;
-            let path = normalize_path(&std::path::Path::new(root).join(name));
-            let path_string = path.to_str().ok_or(super::josh_error("no name"))?;
-            if name == "workspace.josh" {
-                file_contents = format!(
-                    "#{}\n{}",
-                    path_string,
-                    get_blob(repo, &tree, std::path::Path::new(&name))
-                );
-            } else {
-                file_contents = path_string.to_string();
+            let path = normalize_path(&root.join(name));
+            let mut file_contents = path.as_os_str().as_bytes().to_vec();
+            if name == Path::new("workspace.josh") {
+                file_contents.insert(0, b'#');
+                file_contents.push(b'\n');
+                file_contents.extend(get_blob(repo, &tree, name).as_bytes());
             }
-            result = replace_child(
-                repo,
-                std::path::Path::new(name),
-                repo.blob(file_contents.as_bytes())?,
-                0o0100644,
-                &result,
-            )?;
+            result = replace_child(repo, name, repo.blob(&file_contents)?, 0o0100644, &result)?;
         }
 
         if entry.kind() == Some(git2::ObjectType::Tree) {
-            let s = pathstree(
-                &format!("{}{}{}", root, if root.is_empty() { "" } else { "/" }, name),
-                entry.id(),
-                transaction,
-            )?
-            .id();
+            let s = pathstree(&root.join(name), entry.id(), transaction)?.id();
 
             if s != tree::empty_id() {
-                result = replace_child(repo, std::path::Path::new(name), s, 0o0040000, &result)?;
+                result = replace_child(repo, name, s, 0o0040000, &result)?;
             }
         }
     }
-    transaction.insert_paths((input, root.to_string()), result.id());
+    transaction.insert_paths((input, root.to_owned()), result.id())?;
     Ok(result)
 }
 
 pub fn remove_pred<'a>(
     transaction: &'a cache::Transaction,
-    root: &str,
+    root: &Path,
     input: git2::Oid,
-    pred: &dyn Fn(&std::path::Path, bool) -> bool,
+    pred: &dyn Fn(&std::path::Path, Option<git2::Oid>) -> bool,
     key: git2::Oid,
 ) -> super::JoshResult<git2::Tree<'a>> {
     let repo = transaction.repo();
-    if let Some(cached) = transaction.get_glob((input, key)) {
+    if let Some(cached) = transaction.get_glob((input, key))? {
         return Ok(repo.find_tree(cached)?);
     }
-    rs_tracing::trace_scoped!("remove_pred X", "root": root);
+    rs_tracing::trace_scoped!("remove_pred X", "root": root.to_string_lossy());
 
     let tree = repo.find_tree(input)?;
     let mut result = tree::empty(repo);
 
     for entry in tree.iter() {
-        let name = entry.name().ok_or(super::josh_error("INVALID_FILENAME"))?;
-        let path = std::path::PathBuf::from(root).join(name);
+        let name = entry_name(&entry);
+        let path = root.join(name);
 
-        if entry.kind() == Some(git2::ObjectType::Blob) && pred(&path, true) {
-            result = replace_child(
-                repo,
-                std::path::Path::new(entry.name().ok_or(super::josh_error("no name"))?),
-                entry.id(),
-                entry.filemode(),
-                &result,
-            )?;
+        if entry.kind() == Some(git2::ObjectType::Blob) && pred(&path, Some(entry.id())) {
+            result = replace_child(repo, name, entry.id(), entry.filemode(), &result)?;
         }
 
         if entry.kind() == Some(git2::ObjectType::Tree) {
-            let s = if !root.is_empty() && pred(&path, false) {
+            let s = if root != Path::new("") && pred(&path, None) {
                 entry.id()
             } else {
-                remove_pred(
-                    transaction,
-                    &format!(
-                        "{}{}{}",
-                        root,
-                        if root.is_empty() { "" } else { "/" },
-                        entry.name().ok_or(super::josh_error("no name"))?
-                    ),
-                    entry.id(),
-                    &pred,
-                    key,
-                )?
-                .id()
+                remove_pred(transaction, &path, entry.id(), &pred, key)?.id()
             };
 
             if s != tree::empty_id() {
-                result = replace_child(
-                    repo,
-                    std::path::Path::new(entry.name().ok_or(super::josh_error("no name"))?),
-                    s,
-                    0o0040000,
-                    &result,
-                )?;
+                result = replace_child(repo, name, s, 0o0040000, &result)?;
+            }
+        }
+    }
+
+    transaction.insert_glob((input, key), result.id())?;
+    Ok(result)
+}
+
+/// Replace all gitlinks in `input` with the tree of the commit they point to. Nested
+/// submodules are inlined as well. Fails if one of the commits is not available in the repo,
+/// as the result would otherwise depend on what was fetched at the time it was computed.
+pub fn inline_submodules(
+    transaction: &cache::Transaction,
+    root: &Path,
+    input: git2::Oid,
+    key: git2::Oid,
+) -> super::JoshResult<git2::Oid> {
+    let repo = transaction.repo();
+    if let Some(cached) = transaction.get_glob((input, key))? {
+        return Ok(cached);
+    }
+
+    let tree = repo.find_tree(input)?;
+    let mut result = tree.clone();
+
+    for entry in tree.iter() {
+        let name = entry_name(&entry);
+        let inlined = match entry.kind() {
+            Some(git2::ObjectType::Commit) => match repo.find_commit(entry.id()) {
+                Ok(commit) => {
+                    inline_submodules(transaction, &root.join(name), commit.tree_id(), key)?
+                }
+                Err(_) => {
+                    return Err(super::josh_error(&format!(
+                        "commit {} of submodule {:?} is not available",
+                        entry.id(),
+                        normalize_path(&root.join(name))
+                    )))
+                }
+            },
+            Some(git2::ObjectType::Tree) => {
+                inline_submodules(transaction, &root.join(name), entry.id(), key)?
+            }
+            _ => continue,
+        };
+
+        if inlined != entry.id() {
+            result = replace_child(repo, name, inlined, 0o0040000, &result)?;
+        }
+    }
+
+    transaction.insert_glob((input, key), result.id())?;
+    Ok(result.id())
+}
+
+/// Undo `inline_submodules` for a tree pushed to a view: Inlined submodules of `parent_tree`
+/// are turned back into gitlinks, which requires their content to be unchanged.
+pub fn uninline_submodules<'a>(
+    transaction: &'a cache::Transaction,
+    tree: git2::Tree<'a>,
+    parent_tree: &git2::Tree,
+    key: git2::Oid,
+) -> super::JoshResult<git2::Tree<'a>> {
+    let repo = transaction.repo();
+    let mut gitlinks = vec![];
+    walk(repo, Path::new(""), parent_tree, &mut |path, entry| {
+        if entry.kind() == Some(git2::ObjectType::Commit) {
+            gitlinks.push((path.to_owned(), entry.id()));
+        }
+    })?;
+
+    let mut result = tree;
+    for (path, id) in gitlinks {
+        let pushed = ok_or!(result.get_path(&path), { continue }).id();
+        if pushed == id {
+            continue;
+        }
+        let commit = ok_or!(repo.find_commit(id), {
+            return Err(super::josh_error(&format!(
+                "commit {} of submodule {:?} is not available",
+                id,
+                normalize_path(&path)
+            )));
+        });
+        if pushed != inline_submodules(transaction, &path, commit.tree_id(), key)? {
+            return Err(super::josh_error(&format!(
+                "can't push changes to submodule {:?}",
+                path
+            )));
+        }
+        result = insert(repo, &result, &path, id, 0o0160000)?;
+    }
+    Ok(result)
+}
+
+/// Add all blobs and gitlinks in `restore` to `tree`, except for those where `tree`
+/// already has an entry at the same path.
+pub fn restore<'a>(
+    repo: &'a git2::Repository,
+    tree: git2::Tree<'a>,
+    restore: &git2::Tree,
+) -> super::JoshResult<git2::Tree<'a>> {
+    let mut entries = vec![];
+    walk(repo, Path::new(""), restore, &mut |path, entry| {
+        if entry.kind() != Some(git2::ObjectType::Tree) {
+            entries.push((path.to_owned(), entry.id(), entry.filemode()));
+        }
+    })?;
+
+    let mut result = tree;
+    for (path, id, mode) in entries {
+        if result.get_path(&path).is_err() {
+            result = insert(repo, &result, &path, id, mode)?;
+        }
+    }
+    Ok(result)
+}
+
+fn replace_all(replacements: &[(regex::Regex, String)], content: &str) -> String {
+    let mut content = content.to_string();
+    for (regex, replacement) in replacements {
+        content = regex
+            .replace_all(&content, replacement.as_str())
+            .into_owned();
+    }
+    content
+}
+
+/// Apply `replacements` to a blob. Binary and non UTF-8 blobs are returned unchanged.
+fn replace_blob(
+    repo: &git2::Repository,
+    id: git2::Oid,
+    replacements: &[(regex::Regex, String)],
+) -> super::JoshResult<git2::Oid> {
+    let blob = repo.find_blob(id)?;
+    if blob.is_binary() {
+        return Ok(id);
+    }
+    let content = ok_or!(std::str::from_utf8(blob.content()), {
+        return Ok(id);
+    });
+    let replaced = replace_all(replacements, content);
+    if replaced == content {
+        return Ok(id);
+    }
+    Ok(repo.blob(replaced.as_bytes())?)
+}
+
+/// Check if any of `regexes` matches the text content of blob `id`
+fn blob_matches(
+    repo: &git2::Repository,
+    id: git2::Oid,
+    regexes: &[(regex::Regex, String)],
+) -> super::JoshResult<bool> {
+    let blob = repo.find_blob(id)?;
+    if blob.is_binary() {
+        return Ok(false);
+    }
+    let content = ok_or!(std::str::from_utf8(blob.content()), {
+        return Ok(false);
+    });
+    Ok(regexes.iter().any(|(regex, _)| regex.is_match(content)))
+}
+
+pub fn regex_replace<'a>(
+    transaction: &'a cache::Transaction,
+    input: git2::Oid,
+    replacements: &[(regex::Regex, String)],
+    key: git2::Oid,
+) -> super::JoshResult<git2::Tree<'a>> {
+    let repo = transaction.repo();
+    if let Some(cached) = transaction.get_glob((input, key))? {
+        return Ok(repo.find_tree(cached)?);
+    }
+    rs_tracing::trace_scoped!("regex_replace");
+
+    let tree = repo.find_tree(input)?;
+    let mut result = tree.clone();
+
+    for entry in tree.iter() {
+        let name = entry_name(&entry);
+        let id = match entry.kind() {
+            Some(git2::ObjectType::Blob) if entry.filemode() != 0o0120000 => {
+                replace_blob(repo, entry.id(), replacements)?
+            }
+            Some(git2::ObjectType::Tree) => {
+                regex_replace(transaction, entry.id(), replacements, key)?.id()
             }
+            _ => continue,
+        };
+
+        if id != entry.id() {
+            result = replace_child(repo, name, id, entry.filemode(), &result)?;
+        }
+    }
+
+    transaction.insert_glob((input, key), result.id())?;
+    Ok(result)
+}
+
+fn regex_literal(pattern: &str) -> Option<String> {
+    let mut literal = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let c = chars.next()?;
+                if c.is_ascii_alphanumeric() {
+                    return None;
+                }
+                literal.push(c);
+            }
+            '.' | '^' | '$' | '*' | '+' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '|' => {
+                return None
+            }
+            c => literal.push(c),
+        }
+    }
+    Some(literal)
+}
+
+fn replacement_literal(replacement: &str) -> Option<String> {
+    let mut literal = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        if c == '$' && chars.next()? != '$' {
+            return None;
+        }
+        literal.push(c);
+    }
+    Some(literal)
+}
+
+/// Find replacements undoing `replacements`. This is only possible if all of them replace
+/// one plain string with another one.
+pub fn replace_inverse(
+    replacements: &[(regex::Regex, String)],
+) -> Option<Vec<(regex::Regex, String)>> {
+    replacements
+        .iter()
+        .rev()
+        .map(|(regex, replacement)| {
+            let from = regex_literal(regex.as_str())?;
+            let to = replacement_literal(replacement)?;
+            if to.is_empty() {
+                return None;
+            }
+            Some((
+                regex::Regex::new(&regex::escape(&to)).ok()?,
+                from.replace('$', "$$"),
+            ))
+        })
+        .collect()
+}
+
+/// Restore the contents of blobs rewritten by `replacements`.
+/// Blobs that did not change compared to `parent_tree` are taken from there, others are
+/// restored using `inverse`. If that is not possible, the blob has to be left as is by the
+/// replacements, otherwise the change can not be pushed.
+pub fn regex_unreplace<'a>(
+    transaction: &'a cache::Transaction,
+    root: &Path,
+    tree: git2::Tree<'a>,
+    parent_tree: git2::Tree<'a>,
+    replacements: &[(regex::Regex, String)],
+    inverse: Option<&[(regex::Regex, String)]>,
+) -> super::JoshResult<git2::Tree<'a>> {
+    let repo = transaction.repo();
+    let mut result = tree.clone();
+
+    for entry in tree.iter() {
+        let name = entry_name(&entry);
+        let path = normalize_path(&root.join(name));
+        let parent_entry = get_name(&parent_tree, name);
+
+        let id = match entry.kind() {
+            Some(git2::ObjectType::Blob) if entry.filemode() != 0o0120000 => {
+                if let Some(p) = parent_entry
+                    .as_ref()
+                    .filter(|p| p.kind() == Some(git2::ObjectType::Blob))
+                {
+                    if replace_blob(repo, p.id(), replacements)? == entry.id() {
+                        result = replace_child(repo, name, p.id(), entry.filemode(), &result)?;
+                        continue;
+                    }
+                }
+
+                if let (Some(inverse), Some(p)) = (inverse, parent_entry.as_ref()) {
+                    // The inverse would also convert text of the original that already
+                    // looked like the result of the replacements
+                    if p.kind() == Some(git2::ObjectType::Blob)
+                        && blob_matches(repo, p.id(), inverse)?
+                    {
+                        return Err(josh_error(&format!(
+                            "filter not reversible: the original content of {:?} contains \
+                             replacement text",
+                            path
+                        )));
+                    }
+                }
+
+                let restored = if let Some(inverse) = inverse {
+                    replace_blob(repo, entry.id(), inverse)?
+                } else {
+                    entry.id()
+                };
+
+                if replace_blob(repo, restored, replacements)? == entry.id() {
+                    restored
+                } else if replace_blob(repo, entry.id(), replacements)? == entry.id() {
+                    entry.id()
+                } else {
+                    return Err(josh_error(&format!(
+                        "filter not reversible: can't restore the original content of {:?}",
+                        path
+                    )));
+                }
+            }
+            Some(git2::ObjectType::Tree) => {
+                let parent_tree = parent_entry
+                    .and_then(|p| repo.find_tree(p.id()).ok())
+                    .unwrap_or_else(|| tree::empty(repo));
+                regex_unreplace(
+                    transaction,
+                    &path,
+                    repo.find_tree(entry.id())?,
+                    parent_tree,
+                    replacements,
+                    inverse,
+                )?
+                .id()
+            }
+            _ => continue,
+        };
+
+        if id != entry.id() {
+            result = replace_child(repo, name, id, entry.filemode(), &result)?;
         }
     }
 
-    transaction.insert_glob((input, key), result.id());
     Ok(result)
 }
 
@@ -135,7 +463,7 @@ pub fn subtract<'a>(
         return Ok(tree::empty_id());
     }
 
-    if let Some(cached) = transaction.get_subtract((input1, input2)) {
+    if let Some(cached) = transaction.get_subtract((input1, input2))? {
         return Ok(cached);
     }
 
@@ -147,10 +475,10 @@ pub fn subtract<'a>(
         let mut result_tree = tree1.clone();
 
         for entry in tree2.iter() {
-            if let Some(e) = tree1.get_name(entry.name().ok_or(super::josh_error("no name"))?) {
+            if let Some(e) = get_name(&tree1, entry_name(&entry)) {
                 result_tree = replace_child(
                     repo,
-                    std::path::Path::new(entry.name().ok_or(super::josh_error("no name"))?),
+                    entry_name(&entry),
                     subtract(transaction, e.id(), entry.id())?,
                     e.filemode(),
                     &result_tree,
@@ -158,12 +486,12 @@ pub fn subtract<'a>(
             }
         }
 
-        transaction.insert_subtract((input1, input2), result_tree.id());
+        transaction.insert_subtract((input1, input2), result_tree.id())?;
 
         return Ok(result_tree.id());
     }
 
-    transaction.insert_subtract((input1, input2), tree::empty_id());
+    transaction.insert_subtract((input1, input2), tree::empty_id())?;
 
     Ok(tree::empty_id())
 }
@@ -234,10 +562,10 @@ pub fn overlay(
         let mut result_tree = tree1.clone();
 
         for entry in tree2.iter() {
-            if let Some(e) = tree1.get_name(entry.name().ok_or(super::josh_error("no name"))?) {
+            if let Some(e) = get_name(&tree1, entry_name(&entry)) {
                 result_tree = replace_child(
                     repo,
-                    std::path::Path::new(entry.name().ok_or(super::josh_error("no name"))?),
+                    entry_name(&entry),
                     overlay(repo, entry.id(), e.id())?,
                     e.filemode(),
                     &result_tree,
@@ -245,7 +573,7 @@ pub fn overlay(
             } else {
                 result_tree = replace_child(
                     repo,
-                    std::path::Path::new(entry.name().ok_or(super::josh_error("no name"))?),
+                    entry_name(&entry),
                     entry.id(),
                     entry.filemode(),
                     &result_tree,
@@ -259,15 +587,14 @@ pub fn overlay(
     Ok(input2)
 }
 
-pub fn pathline(b: &str) -> JoshResult<String> {
-    for line in b.split('\n') {
-        let l = line.trim_start_matches('#');
-        if l.is_empty() {
-            break;
-        }
-        return Ok(l.to_string());
+/// Read the original path from a blob created by `pathstree`
+pub fn pathline(b: &[u8]) -> JoshResult<std::path::PathBuf> {
+    let line = b.split(|c| *c == b'\n').next().unwrap_or_default();
+    let start = line.iter().take_while(|c| **c == b'#').count();
+    if start == line.len() {
+        return Err(josh_error("pathline"));
     }
-    Err(josh_error("pathline"))
+    Ok(std::ffi::OsString::from_vec(line[start..].to_vec()).into())
 }
 
 const FILE_FILTER_SIZE: usize = 64;
@@ -314,7 +641,7 @@ pub fn trigram_index<'a>(
     tree: git2::Tree<'a>,
 ) -> super::JoshResult<git2::Tree<'a>> {
     let repo = transaction.repo();
-    if let Some(cached) = transaction.get_trigram_index(tree.id()) {
+    if let Some(cached) = transaction.get_trigram_index(tree.id())? {
         return Ok(repo.find_tree(cached)?);
     }
 
@@ -479,7 +806,7 @@ pub fn trigram_index<'a>(
             .unwrap();
         }
     }
-    transaction.insert_trigram_index(tree.id(), result.id());
+    transaction.insert_trigram_index(tree.id(), result.id())?;
     Ok(result)
 }
 
@@ -687,47 +1014,40 @@ pub fn trigram_search<'a>(
 
 pub fn invert_paths<'a>(
     transaction: &'a cache::Transaction,
-    root: &str,
+    root: &Path,
     tree: git2::Tree<'a>,
 ) -> JoshResult<git2::Tree<'a>> {
     let repo = transaction.repo();
-    if let Some(cached) = transaction.get_invert((tree.id(), root.to_string())) {
+    if let Some(cached) = transaction.get_invert((tree.id(), root.to_owned()))? {
         return Ok(repo.find_tree(cached)?);
     }
 
     let mut result = tree::empty(repo);
 
     for entry in tree.iter() {
-        let name = entry.name().ok_or(super::josh_error("no name"))?;
+        let name = entry_name(&entry);
 
         if entry.kind() == Some(git2::ObjectType::Blob) {
-            let mpath = normalize_path(&std::path::Path::new(root).join(name))
-                .to_string_lossy()
-                .to_string();
-            let b = tree::get_blob(repo, &tree, std::path::Path::new(name));
-            let opath = pathline(&b)?;
+            let mpath = normalize_path(&root.join(name));
+            let opath = pathline(repo.find_blob(entry.id())?.content())?;
 
             result = insert(
                 repo,
                 &result,
-                std::path::Path::new(&opath),
-                repo.blob(mpath.as_bytes())?,
+                &opath,
+                repo.blob(mpath.as_os_str().as_bytes())?,
                 0o0100644,
             )
             .unwrap();
         }
 
         if entry.kind() == Some(git2::ObjectType::Tree) {
-            let s = invert_paths(
-                transaction,
-                &format!("{}{}{}", root, if root.is_empty() { "" } else { "/" }, name),
-                repo.find_tree(entry.id())?,
-            )?;
+            let s = invert_paths(transaction, &root.join(name), repo.find_tree(entry.id())?)?;
             result = repo.find_tree(overlay(repo, result.id(), s.id())?)?;
         }
     }
 
-    transaction.insert_invert((tree.id(), root.to_string()), result.id());
+    transaction.insert_invert((tree.id(), root.to_owned()), result.id())?;
 
     Ok(result)
 }
@@ -737,10 +1057,19 @@ pub fn original_path(
     filter: Filter,
     tree: git2::Tree,
     path: &std::path::Path,
-) -> JoshResult<String> {
+) -> JoshResult<std::path::PathBuf> {
+    let repo = transaction.repo();
     let paths_tree = apply(transaction, chain(to_filter(Op::Paths), filter), tree)?;
-    let b = tree::get_blob(transaction.repo(), &paths_tree, path);
-    pathline(&b)
+    let id = paths_tree
+        .get_path(path)
+        .map(|x| x.id())
+        .unwrap_or(git2::Oid::zero());
+    pathline(
+        repo.find_blob(id)
+            .map(|x| x.content().to_vec())
+            .unwrap_or_default()
+            .as_slice(),
+    )
 }
 
 pub fn repopulated_tree(
@@ -751,7 +1080,7 @@ pub fn repopulated_tree(
 ) -> JoshResult<git2::Oid> {
     let paths_tree = apply(transaction, chain(to_filter(Op::Paths), filter), full_tree)?;
 
-    let ipaths = invert_paths(transaction, "", paths_tree)?;
+    let ipaths = invert_paths(transaction, Path::new(""), paths_tree)?;
     populate(transaction, ipaths.id(), partial_tree.id())
 }
 
@@ -762,7 +1091,7 @@ fn populate(
 ) -> super::JoshResult<git2::Oid> {
     rs_tracing::trace_scoped!("repopulate");
 
-    if let Some(cached) = transaction.get_populate((paths, content)) {
+    if let Some(cached) = transaction.get_populate((paths, content))? {
         return Ok(cached);
     }
 
@@ -770,18 +1099,18 @@ fn populate(
 
     let mut result_tree = empty_id();
     if let (Ok(paths), Ok(content)) = (repo.find_blob(paths), repo.find_blob(content)) {
-        let ipath = pathline(std::str::from_utf8(paths.content())?)?;
+        let ipath = pathline(paths.content())?;
         result_tree = insert(
             repo,
             &repo.find_tree(result_tree)?,
-            std::path::Path::new(&ipath),
+            &ipath,
             content.id(),
             0o0100644,
         )?
         .id();
     } else if let (Ok(paths), Ok(content)) = (repo.find_tree(paths), repo.find_tree(content)) {
         for entry in content.iter() {
-            if let Some(e) = paths.get_name(entry.name().ok_or(super::josh_error("no name"))?) {
+            if let Some(e) = get_name(&paths, entry_name(&entry)) {
                 result_tree = overlay(
                     repo,
                     result_tree,
@@ -791,7 +1120,7 @@ fn populate(
         }
     }
 
-    transaction.insert_populate((paths, content), result_tree);
+    transaction.insert_populate((paths, content), result_tree)?;
 
     Ok(result_tree)
 }
@@ -806,22 +1135,22 @@ pub fn compose<'a>(
     let mut taken = tree::empty(repo);
     for (f, applied) in trees {
         let tid = taken.id();
-        let taken_applied = if let Some(cached) = transaction.get_apply(*f, tid) {
+        let taken_applied = if let Some(cached) = transaction.get_apply(*f, tid)? {
             cached
         } else {
             filter::apply(transaction, *f, taken.clone())?.id()
         };
-        transaction.insert_apply(*f, tid, taken_applied);
+        transaction.insert_apply(*f, tid, taken_applied)?;
 
         let subtracted = repo.find_tree(subtract(transaction, applied.id(), taken_applied)?)?;
 
         let aid = applied.id();
-        let unapplied = if let Some(cached) = transaction.get_unapply(*f, aid) {
+        let unapplied = if let Some(cached) = transaction.get_unapply(*f, aid)? {
             cached
         } else {
             filter::unapply(transaction, *f, applied, empty(repo))?.id()
         };
-        transaction.insert_unapply(*f, aid, unapplied);
+        transaction.insert_unapply(*f, aid, unapplied)?;
         taken = repo.find_tree(overlay(repo, taken.id(), unapplied)?)?;
         result = repo.find_tree(overlay(repo, result.id(), subtracted.id())?)?;
     }
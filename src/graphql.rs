@@ -230,13 +230,34 @@ impl Revision {
         }
     }
 
+    fn diff_selection(
+        &self,
+        filter: String,
+        context: &Context,
+    ) -> FieldResult<Vec<SelectionChange>> {
+        let transaction = context.transaction.lock()?;
+        let commit = transaction.repo().find_commit(self.commit_id)?;
+
+        let changes = filter::diff_selection(
+            &transaction,
+            self.filter,
+            filter::parse(&filter)?,
+            commit.tree()?,
+        )?
+        .into_iter()
+        .map(|change| SelectionChange { change })
+        .collect();
+
+        Ok(changes)
+    }
+
     fn warnings(&self, context: &Context) -> FieldResult<Option<Vec<Warning>>> {
         let transaction = context.transaction.lock()?;
         let commit = transaction.repo().find_commit(self.commit_id)?;
 
         let warnings = filter::compute_warnings(&transaction, self.filter, commit.tree()?)
             .into_iter()
-            .map(|text| Warning { text })
+            .map(|warning| Warning { warning })
             .collect();
 
         Ok(Some(warnings))
@@ -289,13 +310,66 @@ impl Revision {
 }
 
 pub struct Warning {
-    text: String,
+    warning: filter::Warning,
 }
 
 #[graphql_object(context = Context)]
 impl Warning {
     fn message(&self) -> &str {
-        &self.text
+        &self.warning.message
+    }
+
+    fn parse_error(&self) -> Option<ParseError> {
+        self.warning
+            .parse_error
+            .clone()
+            .map(|error| ParseError { error })
+    }
+}
+
+pub struct ParseError {
+    error: filter::ParseError,
+}
+
+#[graphql_object(context = Context)]
+impl ParseError {
+    fn message(&self) -> &str {
+        &self.error.message
+    }
+    fn start(&self) -> i32 {
+        self.error.span.start as i32
+    }
+    fn end(&self) -> i32 {
+        self.error.span.end as i32
+    }
+    fn line(&self) -> i32 {
+        self.error.line as i32
+    }
+    fn column(&self) -> i32 {
+        self.error.column as i32
+    }
+    fn rule(&self) -> Option<String> {
+        self.error.rule()
+    }
+    fn suggestions(&self) -> Vec<String> {
+        self.error.suggestions.clone()
+    }
+}
+
+pub struct SelectionChange {
+    change: filter::SelectionChange,
+}
+
+#[graphql_object(context = Context)]
+impl SelectionChange {
+    fn path(&self) -> &str {
+        &self.change.path
+    }
+    fn from(&self) -> Option<&str> {
+        self.change.from.as_deref()
+    }
+    fn to(&self) -> Option<&str> {
+        self.change.to.as_deref()
     }
 }
 
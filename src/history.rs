@@ -11,7 +11,7 @@ pub fn walk2(
         return Ok(());
     });
 
-    if transaction.known(filter, input) {
+    if transaction.known(filter, input)? {
         return Ok(());
     }
 
@@ -151,19 +151,37 @@ fn find_known(
     let mut walk = transaction.repo().revwalk()?;
     walk.push(input)?;
 
+    let mut error = None;
     let n_new = walk
-        .with_hide_callback(&|id| {
-            let k = transaction.known(filter, id);
-            if k {
-                known.push(id)
+        .with_hide_callback(&|id| match transaction.known(filter, id) {
+            Ok(k) => {
+                if k {
+                    known.push(id)
+                }
+                k
+            }
+            Err(e) => {
+                error = Some(e);
+                true
             }
-            k
         })?
         .count();
+    if let Some(e) = error {
+        return Err(e);
+    }
     log::debug!("/find_known {}", n_new);
     Ok((known, n_new))
 }
 
+/// Replacements for the metadata of a commit when rewriting it.
+/// Everything that is `None` is taken from the original commit.
+#[derive(Default)]
+pub struct CommitMeta {
+    pub author: Option<git2::Signature<'static>>,
+    pub committer: Option<git2::Signature<'static>>,
+    pub message: Option<String>,
+}
+
 // takes everything from base except it's tree and replaces it with the tree
 // given
 pub fn rewrite_commit(
@@ -171,24 +189,44 @@ pub fn rewrite_commit(
     base: &git2::Commit,
     parents: &[&git2::Commit],
     tree: &git2::Tree,
+    meta: &CommitMeta,
 ) -> JoshResult<git2::Oid> {
-    if base.tree()?.id() == tree.id() && all_equal(base.parents(), parents) {
+    let author = meta
+        .author
+        .clone()
+        .unwrap_or_else(|| base.author().to_owned());
+    let committer = meta
+        .committer
+        .clone()
+        .unwrap_or_else(|| base.committer().to_owned());
+    let message = meta
+        .message
+        .as_deref()
+        .unwrap_or_else(|| base.message_raw().unwrap_or("no message"));
+
+    if base.tree()?.id() == tree.id()
+        && all_equal(base.parents(), parents)
+        && same_signature(&author, &base.author())
+        && same_signature(&committer, &base.committer())
+        && meta
+            .message
+            .as_deref()
+            .is_none_or(|m| Some(m) == base.message_raw())
+    {
         // Looks like an optimization, but in fact serves to not change the commit in case
         // it was signed.
         return Ok(base.id());
     }
 
-    let b = repo.commit_create_buffer(
-        &base.author(),
-        &base.committer(),
-        base.message_raw().unwrap_or("no message"),
-        tree,
-        parents,
-    )?;
+    let b = repo.commit_create_buffer(&author, &committer, message, tree, parents)?;
 
     return Ok(repo.odb()?.write(git2::ObjectType::Commit, &b)?);
 }
 
+fn same_signature(a: &git2::Signature, b: &git2::Signature) -> bool {
+    a.name_bytes() == b.name_bytes() && a.email_bytes() == b.email_bytes() && a.when() == b.when()
+}
+
 fn all_equal(a: git2::Parents, b: &[&git2::Commit]) -> bool {
     let a: Vec<_> = a.collect();
     if a.len() != b.len() {
@@ -466,11 +504,25 @@ pub fn unapply_filter(
             }
         };
 
+        let (author, committer) = filter::unapply_signatures(
+            transaction,
+            filterobj,
+            new_tree.clone(),
+            &module_commit.author(),
+            &module_commit.committer(),
+        )?;
+        let meta = CommitMeta {
+            author: Some(author),
+            committer: Some(committer),
+            ..Default::default()
+        };
+
         ret = rewrite_commit(
             transaction.repo(),
             &module_commit,
             &original_parents_refs,
             &new_tree,
+            &meta,
         )?;
 
         if let Some(id) = super::get_change_id(&module_commit) {
@@ -497,6 +549,7 @@ pub fn unapply_filter(
                     &module_commit,
                     &original_parents_refs,
                     &transaction.repo().find_tree(merged_tree)?,
+                    &meta,
                 )?;
             }
         }
@@ -534,17 +587,19 @@ pub fn create_filtered_commit<'a>(
     filtered_tree: git2::Tree<'a>,
     transaction: &cache::Transaction,
     filter: filter::Filter,
+    meta: CommitMeta,
 ) -> JoshResult<git2::Oid> {
     let (r, is_new) = create_filtered_commit2(
         transaction.repo(),
         original_commit,
         filtered_parent_ids,
         filtered_tree,
+        &meta,
     )?;
 
     let store = is_new || original_commit.parent_ids().len() != 1;
 
-    transaction.insert(filter, original_commit.id(), r, store);
+    transaction.insert(filter, original_commit.id(), r, store)?;
 
     Ok(r)
 }
@@ -554,6 +609,7 @@ fn create_filtered_commit2<'a>(
     original_commmit: &'a git2::Commit,
     filtered_parent_ids: Vec<git2::Oid>,
     filtered_tree: git2::Tree<'a>,
+    meta: &CommitMeta,
 ) -> JoshResult<(git2::Oid, bool)> {
     let filtered_parent_commits: std::result::Result<Vec<_>, _> = filtered_parent_ids
         .iter()
@@ -599,6 +655,7 @@ fn create_filtered_commit2<'a>(
             original_commmit,
             &selected_filtered_parent_commits,
             &filtered_tree,
+            meta,
         )?,
         true,
     ))
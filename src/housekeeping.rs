@@ -234,15 +234,31 @@ pub fn refresh_known_filters(
     Ok(0)
 }
 
-pub fn run(repo_path: &std::path::Path, do_gc: bool) -> JoshResult<()> {
-    let transaction = cache::Transaction::open(repo_path, None)?;
+/// Rebuild known filters and, if `do_gc` is set, remove unused objects from the repository
+/// and results of filters not used for `cache_max_age` from the cache.
+pub fn run(
+    repo_path: &std::path::Path,
+    do_gc: bool,
+    cache_max_age: std::time::Duration,
+) -> JoshResult<()> {
+    let transaction = cache::Transaction::open(repo_path, None)?.without_access_tracking();
     let known_filters = housekeeping::discover_filter_candidates(&transaction)?;
     refresh_known_filters(&transaction, &known_filters)?;
+    cache::flush()?;
+    // Otherwise `gc` does this as well
+    if !do_gc {
+        cache::update_stats()?;
+    }
     info!(
         "{}",
         run_command(transaction.repo().path(), "git count-objects -v").replace("\n", "  ")
     );
     if do_gc {
+        let stats = cache::gc(&transaction, cache_max_age)?;
+        info!(
+            "cache gc: removed {} entries ({} bytes of keys and values), including all of {} unused filters",
+            stats.entries, stats.bytes, stats.filters
+        );
         info!(
             "\n----------\n{}\n----------",
             run_command(transaction.repo().path(), "git repack -adkbn --threads=1")
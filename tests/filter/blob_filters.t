@@ -0,0 +1,80 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir -p sub1/assets
+  $ echo contents1 > sub1/file1
+  $ head -c 2000 /dev/zero > sub1/assets/large.bin
+  $ printf 'small\0binary' > sub1/assets/small.bin
+  $ head -c 2000 /dev/zero | tr '\0' 'a' > sub1/large.txt
+  $ git add sub1
+  $ git commit -m "add files" 1> /dev/null
+
+  $ josh-filter -s :size\<1K master --update refs/josh/filter/size
+  [1] :size<1K
+  $ git ls-tree -r --name-only refs/josh/filter/size
+  sub1/assets/small.bin
+  sub1/file1
+
+  $ josh-filter -s :text_only master --update refs/josh/filter/text
+  [1] :size<1K
+  [1] :text_only
+  $ git ls-tree -r --name-only refs/josh/filter/text
+  sub1/file1
+  sub1/large.txt
+
+  $ josh-filter -s :/sub1:size\<2000:text_only master --update refs/josh/filter/sub1
+  [1] :/sub1
+  [1] :size<1K
+  [1] :size<2000
+  [2] :text_only
+  $ git ls-tree -r --name-only refs/josh/filter/sub1
+  file1
+
+Pushing restores the excluded files
+
+  $ git checkout -q refs/josh/filter/sub1 -b filtered
+  $ echo contents2 > file2
+  $ git rm -q file1
+  $ git add file2
+  $ git commit -m "add file2" 1> /dev/null
+  $ josh-filter :/sub1:size\<2000:text_only --reverse master --update refs/heads/filtered
+  $ git checkout -q master
+  $ git ls-tree -r --name-only master
+  sub1/assets/large.bin
+  sub1/assets/small.bin
+  sub1/file2
+  sub1/large.txt
+
+Gitlinks are kept in the view and survive a push through it
+
+  $ git update-index --add --cacheinfo 160000,$(git rev-parse HEAD),sub1/module
+  $ git commit -m "add module" 1> /dev/null
+  $ josh-filter :size\<1K master --update refs/josh/filter/size
+  $ git ls-tree -r refs/josh/filter/size sub1/module
+  160000 commit fa564d9d157ed60c3b17bf0bb118befc9113fa6b	sub1/module
+
+  $ git checkout -q refs/josh/filter/size -b filtered_size
+  $ echo contents3 > sub1/file3
+  $ git add sub1/file3
+  $ git commit -m "add file3" 1> /dev/null
+  $ josh-filter :size\<1K --reverse master --update refs/heads/filtered_size
+  $ git checkout -q master
+  $ git ls-tree -r --name-only master
+  sub1/assets/large.bin
+  sub1/assets/small.bin
+  sub1/file2
+  sub1/file3
+  sub1/large.txt
+  sub1/module
+  $ git ls-tree -r master sub1/module
+  160000 commit fa564d9d157ed60c3b17bf0bb118befc9113fa6b	sub1/module
+  $ josh-filter -p :size\<17179869184G
+  ERROR: Filter ":size": invalid size "17179869184G"
+   --> 1:1
+    |
+  1 | :size<17179869184G
+    | ^^^^^^^^^^^^^^^^^^
+  [1]
@@ -0,0 +1,39 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1 sub2
+  $ echo contents1 > sub1/file1
+  $ echo contents2 > sub2/file2
+  $ git add .
+  $ git commit -q -m "add files"
+  $ echo contents3 > sub1/file3
+  $ git add sub1
+  $ git commit -q -m "add file3"
+
+  $ josh-filter --cache-backend=memory :/sub1 master --update refs/josh/memory
+  $ josh-filter --cache-backend=sqlite :/sub1 master --update refs/josh/sqlite
+  $ josh-filter :/sub1 master --update refs/josh/sled
+  $ git rev-parse refs/josh/memory refs/josh/sqlite refs/josh/sled
+  a62d4e1a8fe91bf020e5d3dbe31cce38e3d059e2
+  a62d4e1a8fe91bf020e5d3dbe31cce38e3d059e2
+  a62d4e1a8fe91bf020e5d3dbe31cce38e3d059e2
+
+The SQLite cache is a single file that can be inspected with standard tools
+
+  $ sqlite3 .git/josh/*/cache.sqlite "SELECT tree, count(*) FROM entries GROUP BY tree"
+  :/sub1|2
+  _access|1
+  _stats|1
+
+Results are taken from the cache on the next run
+
+  $ josh-filter -s --cache-backend=sqlite :/sub1 master --update refs/josh/sqlite
+  Warning: reference refs/josh/sqlite wasn't updated
+  [2] :/sub1
+
+  $ josh-filter --cache-backend=other :/sub1 master
+  ERROR: unknown cache backend: "other"
+  [1]
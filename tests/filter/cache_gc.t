@@ -0,0 +1,59 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1 sub2
+  $ echo contents1 > sub1/file1
+  $ echo contents2 > sub2/file2
+  $ git add .
+  $ git commit -q -m "add files"
+  $ echo contents3 > sub1/file3
+  $ git add sub1
+  $ git commit -q -m "add file3"
+
+  $ josh-filter :/sub1 master --update refs/josh/sub1
+  $ josh-filter -s :/sub2 master --update refs/josh/sub2
+  [1] :/sub2
+  [2] :/sub1
+
+Everything is reachable and was used recently
+
+  $ josh-filter -s --cache-gc=3600
+  Removed 0 entries (0 bytes of keys and values), including all of 0 unused filters
+  [1] :/sub2
+  [2] :/sub1
+
+After a force push, the results for the dropped commit are removed
+
+  $ git reset -q --hard HEAD~1
+  $ echo contents4 > sub1/file4
+  $ git add sub1
+  $ git commit -q -m "add file4"
+  $ josh-filter :/sub1 master --update refs/josh/sub1
+  $ josh-filter -s --cache-gc=3600
+  Removed 1 entries (40 bytes of keys and values), including all of 0 unused filters
+  [1] :/sub2
+  [2] :/sub1
+
+Filters that have not been used for longer than the given time are removed completely
+
+  $ sleep 2
+  $ josh-filter :/sub1 master --update refs/josh/sub1
+  Warning: reference refs/josh/sub1 wasn't updated
+  $ josh-filter -s --cache-gc=1
+  Removed 1 entries (40 bytes of keys and values), including all of 1 unused filters
+  [2] :/sub1
+
+Filtering after gc gives the same results as without cache
+
+  $ josh-filter :/sub2 master --update refs/josh/sub2
+  Warning: reference refs/josh/sub2 wasn't updated
+  $ josh-filter -n :/sub2 master --update refs/josh/sub2_fresh
+  $ josh-filter -n :/sub1 master --update refs/josh/sub1_fresh
+  $ git rev-parse refs/josh/sub1 refs/josh/sub1_fresh refs/josh/sub2 refs/josh/sub2_fresh
+  35893132bcdfeb271d6607c3fb45baecba2709a3
+  35893132bcdfeb271d6607c3fb45baecba2709a3
+  25f54a31709f8520b0798c743ee1efcbe47069ce
+  25f54a31709f8520b0798c743ee1efcbe47069ce
@@ -0,0 +1,36 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir -p sub1 sub2 sub3
+  $ echo contents1 > sub1/file1
+  $ echo contents2 > sub1/file2
+  $ echo contents3 > sub2/file3
+  $ echo contents4 > sub3/file4
+  $ git add .
+  $ git commit -m "add files" 1> /dev/null
+
+  $ josh-filter ':[a=:/sub1,b=:/sub2]' --diff-filter ':[a=:/sub1,c=:/sub3]'
+  - sub2/file3 -> b/file3
+  + sub3/file4 -> c/file4
+
+  $ josh-filter ':[a=:/sub1,b=:/sub2]' --diff-filter ':[a=:/sub1::file1,x=:/sub1::file2,b=:/sub2]'
+  ~ sub1/file2: a/file2 -> x/file2
+
+  $ josh-filter ':/sub1' --diff-filter ':/sub1'
+
+  $ josh-filter -g 'query { rev(at: "refs/heads/master", filter: ":/sub1") { diffSelection(filter: ":prefix=x") { path from to } } }'
+  {"rev":{"diffSelection":[{"path":"sub1/file1","from":"file1","to":"x/sub1/file1"},{"path":"sub1/file2","from":"file2","to":"x/sub1/file2"},{"path":"sub2/file3","from":null,"to":"x/sub2/file3"},{"path":"sub3/file4","from":null,"to":"x/sub3/file4"}]}}
+
+Paths that are not valid UTF-8 are escaped
+
+  $ mkdir "$(printf 'caf\351')"
+  $ echo contents5 > "$(printf 'caf\351/file5')"
+  $ git add .
+  $ git commit -m "add non-UTF-8 path" 1> /dev/null
+  $ josh-filter ':/sub1' --diff-filter ':[::sub1/,::caf\xE9/]'
+  + caf\xE9/file5 -> caf\xE9/file5
+  ~ sub1/file1: file1 -> sub1/file1
+  ~ sub1/file2: file2 -> sub1/file2
@@ -0,0 +1,81 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ echo contents1 > file1
+  $ git add file1
+  $ git commit -q -m "add file1"
+  $ echo formatted > file1
+  $ git commit -q -a -m "format" --author "Format Bot <bot@example.com>"
+  $ echo contents2 > file2
+  $ git add file2
+  $ git commit -q -m "add file2"
+  $ echo contents3 > file3
+  $ git add file3
+  $ git commit -q -m "[skip-view] add file3"
+  $ echo contents4 > file4
+  $ git add file4
+  $ git commit -q -m "add file4"
+
+  $ josh-filter -s ":drop_commits(author=bot@*)" master --update refs/josh/filter/no_bots
+  [4] :drop_commits(author="bot@*")
+  $ git log --pretty=%s --stat refs/josh/filter/no_bots
+  add file4
+  
+   file4 | 1 +
+   1 file changed, 1 insertion(+)
+  [skip-view] add file3
+  
+   file3 | 1 +
+   1 file changed, 1 insertion(+)
+  add file2
+  
+   file1 | 2 +-
+   file2 | 1 +
+   2 files changed, 2 insertions(+), 1 deletion(-)
+  add file1
+  
+   file1 | 1 +
+   1 file changed, 1 insertion(+)
+
+The filtered trees match the original ones
+
+  $ test $(git rev-parse master^{tree}) = $(git rev-parse refs/josh/filter/no_bots^{tree})
+
+Filters can be chained to drop commits matching any of them
+
+  $ josh-filter ":drop_commits(author=bot@*):drop_commits(message=^\[skip-view\])" master --update refs/josh/filter/dropped
+  $ git log --pretty=%s refs/josh/filter/dropped
+  add file4
+  add file2
+  add file1
+
+All conditions of one filter have to match, so no commit is dropped when each matches
+only one of them
+
+  $ josh-filter ":drop_commits(author=bot@*,message=\"^\\[skip-view\\]\")" master --update refs/josh/filter/none
+  $ git log --pretty=%s refs/josh/filter/none
+  add file4
+  [skip-view] add file3
+  add file2
+  format
+  add file1
+
+  $ josh-filter ":drop_commits(author=bot@*,message=^format)" master --update refs/josh/filter/both
+  $ git log --pretty=%s refs/josh/filter/both
+  add file4
+  [skip-view] add file3
+  add file2
+  add file1
+
+  $ josh-filter -p ":drop_commits(autor=bot@*)"
+  ERROR: Filter ":drop_commits": unknown field: "autor"
+   --> 1:15
+    |
+  1 | :drop_commits(autor=bot@*)
+    |               ^^^^^^^^^^^
+    |
+    = help: did you mean "author"?
+  [1]
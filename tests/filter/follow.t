@@ -0,0 +1,55 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ printf "line1\nline2\nline3\nline4\n" > sub1/file2
+  $ git add sub1
+  $ git commit -m "add file2" 1> /dev/null
+
+  $ git mv sub1 sub2
+  $ git commit -m "mv sub1 sub2" 1> /dev/null
+
+  $ mkdir libs
+  $ git mv sub2 libs/sub3
+  $ echo line5 >> libs/sub3/file2
+  $ echo contents > unrelated
+  $ git add .
+  $ git commit -m "mv sub2 libs/sub3 and edit" 1> /dev/null
+
+  $ josh-filter -s :follow=/libs/sub3 master --update refs/josh/filter/follow
+  [1] :follow=/sub2
+  [2] :follow=/libs/sub3
+  [2] :follow=/sub1
+  $ git log --pretty=%s --stat refs/josh/filter/follow
+  mv sub2 libs/sub3 and edit
+  
+   file2 | 1 +
+   1 file changed, 1 insertion(+)
+  add file2
+  
+   file2 | 4 ++++
+   1 file changed, 4 insertions(+)
+  add file1
+  
+   file1 | 1 +
+   1 file changed, 1 insertion(+)
+
+Pushing changes the current location
+
+  $ git checkout -q refs/josh/filter/follow -b filtered
+  $ echo contents3 > file3
+  $ git add file3
+  $ git commit -q -m "add file3"
+  $ josh-filter :follow=/libs/sub3 --reverse master --update refs/heads/filtered
+  $ git ls-tree -r --name-only master
+  libs/sub3/file1
+  libs/sub3/file2
+  libs/sub3/file3
+  unrelated
@@ -0,0 +1,99 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1 sub2
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+  $ echo contents2 > sub2/file2
+  $ git add sub2
+  $ git commit -m "add file2" 1> /dev/null
+
+  $ mkdir shared
+  $ cat > shared/base.josh <<EOF
+  > a = :/sub1
+  > EOF
+  $ git add shared
+  $ git commit -m "add base.josh" 1> /dev/null
+
+  $ josh-filter :include=shared/base.josh master --update refs/josh/filter/master
+  $ git ls-tree -r --name-only refs/josh/filter/master
+  a/file1
+  $ git log --graph --pretty=%s refs/josh/filter/master
+  * add file1
+
+Changing the included file adds the history of the new paths
+
+  $ cat > shared/base.josh <<EOF
+  > a = :/sub1
+  > b = :/sub2
+  > EOF
+  $ git add shared
+  $ git commit -m "add sub2 to base.josh" 1> /dev/null
+
+  $ josh-filter :include=shared/base.josh master --update refs/josh/filter/master
+  $ git ls-tree -r --name-only refs/josh/filter/master
+  a/file1
+  b/file2
+  $ git log --graph --pretty=%s refs/josh/filter/master
+  *   add sub2 to base.josh
+  |\  
+  | * add file2
+  * add file1
+
+The included filter can be combined with others
+
+  $ josh-filter -p ':[c=:/shared,:include=shared/base.josh]'
+  :[
+      c = :/shared
+      :include=shared/base.josh
+  ]
+
+Pushing uses the filter from the original tree
+
+  $ git checkout -q refs/josh/filter/master -b filtered
+  $ echo contents3 > b/file3
+  $ git add b
+  $ git commit -m "add file3" 1> /dev/null
+  $ josh-filter :include=shared/base.josh --reverse master --update refs/heads/filtered
+  $ git ls-tree -r --name-only master
+  shared/base.josh
+  sub1/file1
+  sub2/file2
+  sub2/file3
+
+  $ josh-filter :include master
+  ERROR: Filter ":include" requires an argument
+   --> 1:1
+    |
+  1 | :include
+    | ^^^^^^^^
+    |
+    = help: use "=" to provide the argument value: ":include=path", where `path` is path to a file containing a filter spec
+  [1]
+
+Files that include themselves, directly or through others, are an error
+
+  $ git checkout -q master
+  $ cat > shared/self.josh <<EOF
+  > :include=shared/self.josh
+  > EOF
+  $ cat > shared/a.josh <<EOF
+  > a = :/sub1
+  > :include=shared/b.josh
+  > EOF
+  $ cat > shared/b.josh <<EOF
+  > :include=shared/a.josh
+  > EOF
+  $ git add shared
+  $ git commit -m "add cyclic includes" 1> /dev/null
+
+  $ josh-filter :include=shared/self.josh master --update refs/josh/filter/self
+  ERROR: include cycle: shared/self.josh -> shared/self.josh
+  [1]
+  $ josh-filter :include=shared/a.josh master --update refs/josh/filter/a
+  ERROR: include cycle: shared/a.josh -> shared/b.josh -> shared/a.josh
+  [1]
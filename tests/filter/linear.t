@@ -0,0 +1,92 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ git checkout -q -b feature
+  $ echo contents2 > sub1/file2
+  $ git add sub1
+  $ git commit -m "add file2" 1> /dev/null
+  $ echo contents3 > sub1/file3
+  $ git add sub1
+  $ git commit -m "add file3" 1> /dev/null
+
+  $ git checkout -q master
+  $ echo contents4 > sub1/file4
+  $ git add sub1
+  $ git commit -m "add file4" 1> /dev/null
+  $ git merge -q feature --no-ff -m "Merge branch 'feature'"
+
+  $ echo contents5 > sub1/file5
+  $ git add sub1
+  $ git commit -m "add file5" 1> /dev/null
+
+  $ git log --graph --pretty=%s
+  * add file5
+  *   Merge branch 'feature'
+  |\  
+  | * add file3
+  | * add file2
+  * | add file4
+  |/  
+  * add file1
+
+  $ josh-filter -s :linear master --update refs/josh/filter/master
+  [6] :linear
+  $ git log --graph --pretty=%s refs/josh/filter/master
+  * add file5
+  * Merge branch 'feature'
+  * add file4
+  * add file1
+  $ git log -1 --pretty=%B refs/josh/filter/master~1
+  Merge branch 'feature'
+  
+  * add file2
+  * add file3
+  
+  $ git diff master refs/josh/filter/master
+
+  $ josh-filter -s :/sub1:linear master --update refs/josh/filter/sub1
+  [6] :/sub1
+  [12] :linear
+  $ git log --graph --pretty=%s refs/josh/filter/sub1
+  * add file5
+  * Merge branch 'feature'
+  * add file4
+  * add file1
+
+Pushing maps onto the first-parent chain
+
+  $ git checkout -q refs/josh/filter/sub1 -b filtered
+  $ echo contents6 > file6
+  $ git add file6
+  $ git commit -m "add file6" 1> /dev/null
+  $ josh-filter :/sub1:linear --reverse master --update refs/heads/filtered
+  $ git checkout -q master
+  $ git log --graph --pretty=%s
+  * add file6
+  * add file5
+  *   Merge branch 'feature'
+  |\  
+  | * add file3
+  | * add file2
+  * | add file4
+  |/  
+  * add file1
+  $ tree
+  .
+  `-- sub1
+      |-- file1
+      |-- file2
+      |-- file3
+      |-- file4
+      |-- file5
+      `-- file6
+  
+  1 directory, 6 files
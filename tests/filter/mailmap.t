@@ -0,0 +1,69 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ cat > .mailmap <<EOF
+  > # Public identities
+  > Public Dev <dev@example.com> Alice <alice@corp.internal>
+  > <bob@example.com> <bob@corp.internal>
+  > EOF
+  $ git add .mailmap
+  $ git commit -m "add mailmap" 1> /dev/null
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ GIT_AUTHOR_NAME=Alice GIT_AUTHOR_EMAIL=alice@corp.internal git commit -m "add file1" 1> /dev/null
+
+  $ echo contents2 > sub1/file2
+  $ git add sub1
+  $ GIT_AUTHOR_NAME=Bob GIT_AUTHOR_EMAIL=bob@corp.internal GIT_COMMITTER_NAME=Bob GIT_COMMITTER_EMAIL=bob@corp.internal git commit -m "add file2" 1> /dev/null
+
+  $ git log --pretty="%s: %an <%ae> %cn <%ce>"
+  add file2: Bob <bob@corp.internal> Bob <bob@corp.internal>
+  add file1: Alice <alice@corp.internal> Josh <josh@example.com>
+  add mailmap: Josh <josh@example.com> Josh <josh@example.com>
+
+  $ josh-filter -s :mailmap=.mailmap master --update refs/josh/filter/master
+  [3] :mailmap=.mailmap
+  $ git log refs/josh/filter/master --pretty="%s: %an <%ae> %cn <%ce>"
+  add file2: Bob <bob@example.com> Bob <bob@example.com>
+  add file1: Public Dev <dev@example.com> Josh <josh@example.com>
+  add mailmap: Josh <josh@example.com> Josh <josh@example.com>
+  $ git diff master refs/josh/filter/master
+
+The path is relative to the input of the filter
+
+  $ josh-filter ':/sub1:mailmap=.mailmap' master --update refs/josh/filter/sub1
+  $ git log refs/josh/filter/sub1 --pretty="%s: %an <%ae> %cn <%ce>"
+  add file2: Bob <bob@corp.internal> Bob <bob@corp.internal>
+  add file1: Alice <alice@corp.internal> Josh <josh@example.com>
+  $ josh-filter ':mailmap=.mailmap:/sub1' master --update refs/josh/filter/sub1
+  $ git log refs/josh/filter/sub1 --pretty="%s: %an <%ae> %cn <%ce>"
+  add file2: Bob <bob@example.com> Bob <bob@example.com>
+  add file1: Public Dev <dev@example.com> Josh <josh@example.com>
+
+Pushing restores the original identities
+
+  $ git checkout -q refs/josh/filter/sub1 -b filtered
+  $ echo contents3 > file3
+  $ git add file3
+  $ GIT_AUTHOR_NAME="Public Dev" GIT_AUTHOR_EMAIL=dev@example.com GIT_COMMITTER_NAME="Bob" GIT_COMMITTER_EMAIL=bob@example.com git commit -m "add file3" 1> /dev/null
+  $ echo contents4 > file4
+  $ git add file4
+  $ GIT_AUTHOR_NAME="Someone" GIT_AUTHOR_EMAIL=someone@example.com git commit -m "add file4" 1> /dev/null
+  $ josh-filter ':mailmap=.mailmap:/sub1' --reverse master --update refs/heads/filtered
+  $ git log master --pretty="%s: %an <%ae> %cn <%ce>"
+  add file4: Someone <someone@example.com> Josh <josh@example.com>
+  add file3: Alice <alice@corp.internal> Bob <bob@corp.internal>
+  add file2: Bob <bob@corp.internal> Bob <bob@corp.internal>
+  add file1: Alice <alice@corp.internal> Josh <josh@example.com>
+  add mailmap: Josh <josh@example.com> Josh <josh@example.com>
+  $ git ls-tree --name-only -r master
+  .mailmap
+  sub1/file1
+  sub1/file2
+  sub1/file3
+  sub1/file4
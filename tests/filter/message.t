@@ -0,0 +1,90 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ echo contents2 > sub1/file2
+  $ git add sub1
+  $ cat > message <<EOF
+  > add file2
+  > 
+  > This adds the second file.
+  > 
+  > Ticket: https://tickets.corp.internal/1234
+  > Reviewed-by: Alice <alice@example.com>
+  > EOF
+  $ git commit -F message 1> /dev/null
+
+  $ josh-filter -s ':message("{summary}\n\n{body}\n\n{trailers}Josh-Origin: {original_id}","^Ticket:")' master --update refs/josh/filter/master
+  [2] :message("{summary}\n\n{body}\n\n{trailers}Josh-Origin: {original_id}","^Ticket:")
+  $ git log --pretty=%B refs/josh/filter/master
+  add file2
+  
+  This adds the second file.
+  
+  Reviewed-by: Alice <alice@example.com>
+  Josh-Origin: 0596329450cce8676f56b73aa966774767957e01
+  
+  add file1
+  
+  Josh-Origin: bb282e9cdc1b972fffd08fd21eead43bc0c83cb8
+  
+  $ git log --pretty=%H master
+  0596329450cce8676f56b73aa966774767957e01
+  bb282e9cdc1b972fffd08fd21eead43bc0c83cb8
+  $ git diff master refs/josh/filter/master
+
+  $ josh-filter ':message("[{summary}]"):/sub1' master --update refs/josh/filter/sub1
+  $ git log --pretty=%B refs/josh/filter/sub1
+  [add file2]
+  
+  [add file1]
+  
+  $ josh-filter -p ':message(
+  > "{summary}"
+  > "^Ticket:"
+  > )'
+  :message("{summary}","^Ticket:")
+
+Blank lines in the substituted text are kept
+
+  $ echo contents3 > sub1/file3
+  $ git add sub1
+  $ printf "add file3\n\nfirst paragraph\n\n\nsecond paragraph\n" > message
+  $ git commit -q --cleanup=verbatim -F message
+  $ josh-filter ':message("{summary}\n\n{body}\n\n{trailers}")' master --update refs/josh/filter/body
+  $ git log --pretty=%B refs/josh/filter/body
+  add file3
+  
+  first paragraph
+  
+  
+  second paragraph
+  
+  add file2
+  
+  This adds the second file.
+  
+  Ticket: https://tickets.corp.internal/1234
+  Reviewed-by: Alice <alice@example.com>
+  
+  add file1
+  
+
+A template is required
+
+  $ josh-filter -s :message master
+  ERROR: Filter ":message" requires an argument
+   --> 1:1
+    |
+  1 | :message
+    | ^^^^^^^^
+    |
+    = help: use parentheses to provide the template and the trailers to remove: ":message("template","regex",...)", where `template` can contain {summary}, {body}, {trailers} and {original_id}
+  [1]
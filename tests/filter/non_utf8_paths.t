@@ -0,0 +1,63 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir "$(printf 'caf\351')"
+  $ echo contents1 > "$(printf 'caf\351/file\3751')"
+  $ echo contents2 > "$(printf 'caf\351/plain')"
+  $ echo contents3 > "$(printf 'na\357ve')"
+  $ mkdir "sp ace"
+  $ echo contents4 > "sp ace/file"
+  $ git add .
+  $ git commit -q -m "add files"
+
+  $ git ls-tree -r --name-only master
+  "caf\351/file\3751"
+  "caf\351/plain"
+  "na\357ve"
+  sp ace/file
+
+  $ josh-filter -p ":/caf\xE9"
+  :/caf\xE9
+  $ josh-filter -p ":[::na\xEFve,:/sp\x20ace:prefix=caf\xE9]"
+  :[
+      ::na\xEFve
+      caf\xE9 = :/sp\x20ace
+  ]
+
+  $ josh-filter -s ":/caf\xE9" master --update refs/josh/filter/subdir
+  [1] :/caf\xE9
+  $ git ls-tree -r --name-only refs/josh/filter/subdir
+  "file\3751"
+  plain
+
+  $ josh-filter ":[::na\xEFve,:/sp\x20ace:prefix=caf\xE9]" master --update refs/josh/filter/compose
+  $ git ls-tree -r --name-only refs/josh/filter/compose
+  "caf\351/file"
+  "na\357ve"
+
+  $ josh-filter :PATHS master --update refs/josh/filter/paths
+  $ git show refs/josh/filter/paths:"$(printf 'caf\351/file\3751')" | cat -v
+  cafM-i/fileM-}1 (no-eol)
+
+Pushing changes back keeps the original names
+
+  $ git checkout -q refs/josh/filter/subdir -b filtered
+  $ echo contents5 > "$(printf 'new\377')"
+  $ echo changed > "$(printf 'file\3751')"
+  $ git add .
+  $ git commit -q -m "add new file"
+  $ josh-filter ":/caf\xE9" --reverse master --update refs/heads/filtered
+  $ git log --pretty=%s --name-status master
+  add new file
+  
+  M	"caf\351/file\3751"
+  A	"caf\351/new\377"
+  add files
+  
+  A	"caf\351/file\3751"
+  A	"caf\351/plain"
+  A	"na\357ve"
+  A	sp ace/file
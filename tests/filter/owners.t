@@ -0,0 +1,91 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir -p .github docs/internal src/lib src/ui
+  $ echo contents > README.md
+  $ echo contents > docs/guide.md
+  $ echo contents > docs/internal/notes.md
+  $ echo contents > src/lib/lib.rs
+  $ echo contents > src/lib/Cargo.toml
+  $ echo contents > src/ui/main.rs
+  $ cat > .github/CODEOWNERS <<EOF
+  > # Default owners
+  > *              @org/core
+  > *.md           @org/docs @Org/Core # documentation
+  > /docs/internal/
+  > /src/          @org/core @org/lib
+  > src/ui/        @org/ui
+  > Cargo.toml     @org/release
+  > EOF
+  $ git add .
+  $ git commit -m "add files" 1> /dev/null
+
+  $ josh-filter -s :owners=@org/core master --update refs/josh/filter/core
+  [1] :owners=@org/core
+  $ git ls-tree -r --name-only refs/josh/filter/core
+  .github/CODEOWNERS
+  README.md
+  docs/guide.md
+  src/lib/lib.rs
+  $ josh-filter -s :owners=@org/lib master --update refs/josh/filter/lib
+  [1] :owners=@org/core
+  [1] :owners=@org/lib
+  $ git ls-tree -r --name-only refs/josh/filter/lib
+  src/lib/lib.rs
+
+Changing the owners changes the view
+
+  $ echo "/src/lib/      @org/lib" >> .github/CODEOWNERS
+  $ git commit -a -m "move lib" 1> /dev/null
+  $ josh-filter -s :owners=@org/lib master --update refs/josh/filter/lib
+  [1] :owners=@org/core
+  [2] :owners=@org/lib
+  $ git ls-tree -r --name-only refs/josh/filter/lib
+  src/lib/Cargo.toml
+  src/lib/lib.rs
+  $ git log --pretty=%s refs/josh/filter/lib
+  move lib
+  add files
+
+Pushing only changes the paths owned by the team
+
+  $ git checkout -q refs/josh/filter/lib -b filtered
+  $ echo changed > src/lib/lib.rs
+  $ echo contents > src/lib/new.rs
+  $ echo contents > other.txt
+  $ git add .
+  $ git commit -m "edit view" 1> /dev/null
+  $ josh-filter :owners=@org/lib --reverse master --update refs/heads/filtered
+  $ git checkout -q master
+  $ git ls-tree -r --name-only master
+  .github/CODEOWNERS
+  README.md
+  docs/guide.md
+  docs/internal/notes.md
+  src/lib/Cargo.toml
+  src/lib/lib.rs
+  src/lib/new.rs
+  src/ui/main.rs
+  $ cat src/lib/lib.rs
+  changed
+
+A wildcard in the last component only matches the files directly inside a directory
+
+  $ echo "docs/*         @org/writers" >> .github/CODEOWNERS
+  $ git commit -a -m "add writers" 1> /dev/null
+  $ josh-filter :owners=@org/writers master --update refs/josh/filter/writers
+  $ git ls-tree -r --name-only refs/josh/filter/writers
+  docs/guide.md
+
+  $ josh-filter -p ':owners'
+  ERROR: Filter ":owners" requires an argument
+   --> 1:1
+    |
+  1 | :owners
+    | ^^^^^^^
+    |
+    = help: use "=" to provide the argument value: ":owners=@team", where `@team` is an owner as listed in the CODEOWNERS file
+  [1]
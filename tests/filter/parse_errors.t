@@ -0,0 +1,85 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ josh-filter -p ':prefx=a'
+  ERROR: Invalid filter: ":prefx"
+   --> 1:1
+    |
+  1 | :prefx=a
+    | ^^^^^^^^
+    |
+    = help: did you mean "prefix"?
+    = help: use forward slash at the start of the filter if you're trying to select a subdirectory: ":/prefx"
+  [1]
+
+  $ josh-filter -p ':size<abc'
+  ERROR: expected a size such as `100K`
+   --> 1:7
+    |
+  1 | :size<abc
+    |       ^
+  [1]
+
+  $ josh-filter -p ':[a=:/sub1,b=:exclud[::*.md]]'
+  ERROR: Invalid filter: ":exclud"
+   --> 1:14
+    |
+  1 | :[a=:/sub1,b=:exclud[::*.md]]
+    |              ^^^^^^^^^^^^^^^
+    |
+    = help: did you mean "exclude"?
+  [1]
+
+  $ josh-filter -p ':[a=:/sub1,b=:replace("(":"x")]'
+  ERROR: regex parse error:
+      (
+      ^
+  error: unclosed group
+   --> 1:23
+    |
+  1 | :[a=:/sub1,b=:replace("(":"x")]
+    |                       ^^^
+  [1]
+
+  $ cat > ws.josh <<EOF
+  > let common = :/sub1
+  > 
+  > a = :@common
+  > b = :sub2/x
+  > EOF
+  $ josh-filter -p --file ws.josh
+  ERROR: expected a filter such as `:/path`
+   --> 4:10
+    |
+  4 | b = :sub2/x
+    |          ^
+    |
+    = help: use forward slash at the start of the filter if you're trying to select a subdirectory: ":/sub2/x"
+  [1]
+
+  $ cat > ws.josh <<EOF
+  > let common = :/sub1
+  > a = :@comon
+  > EOF
+  $ josh-filter -p --file ws.josh
+  ERROR: Unknown filter binding: ":@comon"
+   --> 2:5
+    |
+  2 | a = :@comon
+    |     ^^^^^^^
+    |
+    = help: did you mean "common"?
+  [1]
+
+  $ git add ws.josh
+  $ git commit -m "add broken filter file" 1> /dev/null
+  $ josh-filter :include=ws.josh -g 'query { rev(at: "refs/heads/master", filter: ":include=ws.josh") { warnings { message parseError { line column start end rule suggestions } } } }'
+  {"rev":{"warnings":[{"message":"couldn't parse ws.josh:\nUnknown filter binding: \":@comon\"\n --> 2:5\n  |\n2 | a = :@comon\n  |     ^^^^^^^\n  |\n  = help: did you mean \"common\"?","parseError":{"line":2,"column":5,"start":24,"end":31,"rule":"filter_ref","suggestions":["did you mean \"common\"?"]}}]}}
@@ -0,0 +1,84 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir -p docs/internal src/build src/lib tools
+  $ echo contents > README.md
+  $ echo contents > docs/guide.md
+  $ echo contents > docs/internal/notes.md
+  $ echo contents > src/lib/lib.rs
+  $ echo contents > src/lib/README.md
+  $ echo contents > src/build/out.o
+  $ echo contents > tools/build
+  $ echo contents > 'tools/#notes'
+  $ cat > .view <<EOF
+  > # The public parts of the repo
+  > *.md
+  > !/docs/internal/**
+  > !src/**/README.md
+  > /src/lib
+  > build/
+  > \#notes
+  > EOF
+  $ git add .
+  $ git commit -m "add files" 1> /dev/null
+
+  $ josh-filter -s :patterns=.view master --update refs/josh/filter/view
+  [1] :patterns=.view
+  $ git ls-tree -r --name-only refs/josh/filter/view
+  README.md
+  docs/guide.md
+  src/build/out.o
+  src/lib/README.md
+  src/lib/lib.rs
+  tools/#notes
+
+Changing the patterns changes the view
+
+  $ cat > .view <<EOF
+  > src/
+  > !src/lib/
+  > EOF
+  $ git commit -a -m "change view" 1> /dev/null
+  $ josh-filter -s :patterns=.view master --update refs/josh/filter/view
+  [2] :patterns=.view
+  $ git ls-tree -r --name-only refs/josh/filter/view
+  src/build/out.o
+  src/lib/README.md
+  src/lib/lib.rs
+  $ git log --pretty=%s refs/josh/filter/view
+  change view
+  add files
+
+Pushing only changes the paths that are part of the view
+
+  $ git checkout -q refs/josh/filter/view -b filtered
+  $ git rm -q src/build/out.o
+  $ echo contents > src/lib/new.rs
+  $ echo contents > other.txt
+  $ git add .
+  $ git commit -m "edit view" 1> /dev/null
+  $ josh-filter :patterns=.view --reverse master --update refs/heads/filtered
+  $ git checkout -q master
+  $ git ls-tree -r --name-only master
+  .view
+  README.md
+  docs/guide.md
+  docs/internal/notes.md
+  src/lib/README.md
+  src/lib/lib.rs
+  src/lib/new.rs
+  tools/#notes
+  tools/build
+
+  $ josh-filter -p ':patterns'
+  ERROR: Filter ":patterns" requires an argument
+   --> 1:1
+    |
+  1 | :patterns
+    | ^^^^^^^^^
+    |
+    = help: use "=" to provide the argument value: ":patterns=path", where `path` is path to a file in .gitignore format
+  [1]
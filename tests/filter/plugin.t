@@ -0,0 +1,118 @@
+  $ export TESTTMP=${PWD}
+
+  $ mkdir ${TESTTMP}/bin
+  $ export PATH=${TESTTMP}/bin:${PATH}
+  $ cat > ${TESTTMP}/bin/josh-plugin-upper <<'EOF'
+  > #!/usr/bin/env python3
+  > import sys
+  > if sys.argv[1:] == ["--version"]:
+  >     print("1")
+  >     sys.exit(0)
+  > stdin, stdout = sys.stdin.buffer, sys.stdout.buffer
+  > assert stdin.readline().startswith(b"tree ")
+  > for header in iter(stdin.readline, b""):
+  >     _, mode, size, path = header.decode().rstrip("\n").split(" ", 3)
+  >     content = stdin.read(int(size) + 1)[:-1]
+  >     if not path.endswith("." + sys.argv[1]):
+  >         continue
+  >     content = content.upper()
+  >     stdout.write(("blob %s %d %s\n" % (mode, len(content), path)).encode())
+  >     stdout.write(content + b"\n")
+  > EOF
+  $ chmod +x ${TESTTMP}/bin/josh-plugin-upper
+  $ cat > ${TESTTMP}/bin/josh-plugin-bad <<'EOF'
+  > #!/usr/bin/env python3
+  > import sys, time
+  > if sys.argv[1:] == ["--version"]:
+  >     print("1")
+  >     sys.exit(0)
+  > sys.stdin.buffer.read()
+  > if sys.argv[1] == "sleep":
+  >     time.sleep(10)
+  > elif sys.argv[1] == "path":
+  >     sys.stdout.write("blob 100644 1 a/../../x\nx\n")
+  > elif sys.argv[1] == "mode":
+  >     sys.stdout.write("blob 160000 1 x\nx\n")
+  > EOF
+  $ chmod +x ${TESTTMP}/bin/josh-plugin-bad
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1.txt
+  $ echo contents2 > sub1/file2.md
+  $ git add sub1
+  $ git commit -m "add files" 1> /dev/null
+
+Plugins are only run when explicitly allowed
+
+  $ josh-filter :/sub1:plugin=upper,txt master --update refs/josh/filter/master
+  ERROR: plugin "upper" is not allowed
+  [1]
+
+  $ josh-filter -s --plugin=upper :/sub1:plugin=upper,txt master --update refs/josh/filter/master
+  [1] :/sub1
+  [1] :plugin=upper,txt
+  [1] _plugin
+  $ git ls-tree -r --name-only refs/josh/filter/master
+  file1.txt
+  $ git show refs/josh/filter/master:file1.txt
+  CONTENTS1
+
+  $ josh-filter --plugin=missing :plugin=missing master
+  ERROR: can't run plugin "missing": josh-plugin-missing not found
+  [1]
+
+  $ josh-filter -n --plugin=other :/sub1:plugin=upper,txt master --update refs/josh/filter/master
+  ERROR: plugin "upper" is not allowed
+  [1]
+  $ josh-filter -n --plugin=upper --plugin=other :/sub1:plugin=upper,txt master --update refs/josh/filter/master
+  Warning: reference refs/josh/filter/master wasn't updated
+
+Plugins can only output files at relative paths, with the modes git uses for files
+
+  $ josh-filter -n --plugin=bad :plugin=bad,path master
+  ERROR: plugin "bad": invalid record: "blob 100644 1 a/../../x\n"
+  [1]
+  $ josh-filter -n --plugin=bad :plugin=bad,mode master
+  ERROR: plugin "bad": invalid record: "blob 160000 1 x\n"
+  [1]
+
+Plugins that take too long are killed
+
+  $ josh-filter -n --plugin=bad --plugin-timeout=1 :plugin=bad,sleep master
+  ERROR: plugin "bad" did not finish within 1s
+  [1]
+
+Plugin names are part of the name of the executable, so paths are not allowed
+
+  $ josh-filter :plugin=../upper master
+  ERROR: Invalid plugin name: "../upper"
+   --> 1:1
+    |
+  1 | :plugin=../upper
+    | ^^^^^^^^^^^^^^^^
+    |
+    = help: plugin names may only contain letters, digits, "_" and "-"
+  [1]
+  $ josh-filter :plugin=up.per master
+  ERROR: Invalid plugin name: "up.per"
+   --> 1:1
+    |
+  1 | :plugin=up.per
+    | ^^^^^^^^^^^^^^
+    |
+    = help: plugin names may only contain letters, digits, "_" and "-"
+  [1]
+
+  $ josh-filter -s :plugin master
+  ERROR: Filter ":plugin" requires an argument
+   --> 1:1
+    |
+  1 | :plugin
+    | ^^^^^^^
+    |
+    = help: use "=" to provide the argument value: ":plugin=name,arg1,arg2", where `name` is the name of the plugin, which runs the executable `josh-plugin-name`
+  [1]
@@ -84,6 +84,8 @@
       :/b
       :/c
   ]
+  Warning: the filter is not reversible
+    :subtract[:/b,:/c]: filter not reversible
 
   $ cat > f <<EOF
   > :subtract[
@@ -186,6 +188,8 @@
       :/a/x/g
       :/m/bs/m2/i/tc/i1
   ]
+  Warning: the filter is not reversible
+    :subtract[:/a/x/g,:/m/bs/m2/i/tc/i1]: filter not reversible
 
   $ cat > f <<EOF
   > a/subsub1 = :/sub1/subsub1
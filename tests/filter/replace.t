@@ -0,0 +1,92 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ cat > sub1/main.go <<EOF
+  > import "internal.corp/lib/a"
+  > import "internal.corp/lib/b"
+  > EOF
+  $ echo "see https://internal.corp/docs" > sub1/README
+  $ printf "internal.corp\0" > sub1/binary
+  $ git add sub1
+  $ git commit -m "add files" 1> /dev/null
+
+  $ josh-filter -s ':/sub1:replace("internal\.corp":"example.com")' master --update refs/josh/filter/master
+  [1] :/sub1
+  [1] :replace("internal\\.corp":"example.com")
+  $ git log refs/josh/filter/master --graph --pretty=%s
+  * add files
+  $ git show refs/josh/filter/master:main.go
+  import "example.com/lib/a"
+  import "example.com/lib/b"
+  $ git show refs/josh/filter/master:README
+  see https://example.com/docs
+  $ git diff --stat refs/josh/filter/master master:sub1 -- binary
+
+  $ josh-filter -p ':replace("a\"b":"c\\","\d+":"<$0>")'
+  :replace("a\"b":"c\\","\\d+":"<$0>")
+
+Edits to the view are converted back when pushing
+
+  $ git checkout -q refs/josh/filter/master -b filtered
+  $ echo 'import "example.com/lib/c"' >> main.go
+  $ git commit -q -a -m "import c" 1> /dev/null
+  $ josh-filter ':/sub1:replace("internal\.corp":"example.com")' --reverse master --update refs/heads/filtered
+  $ git checkout -q master
+  $ git log --graph --pretty=%s master
+  * import c
+  * add files
+  $ cat sub1/main.go
+  import "internal.corp/lib/a"
+  import "internal.corp/lib/b"
+  import "internal.corp/lib/c"
+  $ cat sub1/README
+  see https://internal.corp/docs
+
+Replacements that can't be undone only allow pushing content they leave unchanged
+
+  $ josh-filter ':/sub1:replace("internal\.(corp|net)":"example.com")' master --update refs/heads/filtered2
+  $ git checkout -q filtered2
+  $ echo "new file" > new_file
+  $ git add new_file
+  $ git commit -q -m "add new_file" 1> /dev/null
+  $ josh-filter ':/sub1:replace("internal\.(corp|net)":"example.com")' --reverse master --update refs/heads/filtered2
+  $ git ls-tree --name-only -r master
+  sub1/README
+  sub1/binary
+  sub1/main.go
+  sub1/new_file
+  $ git show master:sub1/main.go
+  import "internal.corp/lib/a"
+  import "internal.corp/lib/b"
+  import "internal.corp/lib/c"
+
+  $ echo "internal.net" >> new_file
+  $ git commit -q -a -m "edit new_file" 1> /dev/null
+  $ josh-filter ':/sub1:replace("internal\.(corp|net)":"example.com")' --reverse master --update refs/heads/filtered2
+  ERROR: 
+  Can't apply "edit new_file" (7ec0b4dff24d48cc2bb64364df6086b3e35d4428)
+  filter not reversible: can't restore the original content of "new_file"
+  [1]
+
+Text of the original that already looks like the result of the replacements can't be
+told apart from replaced text
+
+  $ git checkout -q master
+  $ echo "see https://example.com/more" >> sub1/README
+  $ git commit -q -a -m "mention example.com" 1> /dev/null
+  $ josh-filter ':/sub1:replace("internal\.corp":"example.com")' master --update refs/heads/filtered3
+  $ git checkout -q filtered3
+  $ echo "end" >> README
+  $ git commit -q -a -m "edit README" 1> /dev/null
+  $ josh-filter ':/sub1:replace("internal\.corp":"example.com")' --reverse master --update refs/heads/filtered3
+  ERROR: 
+  Can't apply "edit README" (fc6afc55127e5b974525adac51ddce4c637550d4)
+  filter not reversible: the original content of "README" contains replacement text
+  [1]
+  $ git show master:sub1/README
+  see https://internal.corp/docs
+  see https://example.com/more
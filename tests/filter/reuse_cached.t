@@ -0,0 +1,65 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ for n in 1 2 3; do
+  >   mkdir sub$n
+  >   echo contents$n > sub$n/file
+  >   git add sub$n
+  >   git commit -q -m "add sub$n"
+  > done
+
+  $ josh-filter -s :[a=:/sub1,b=:/sub2] master --update refs/josh/filter/ab
+  [1] :/sub1
+  [1] :prefix=a
+  [1] :prefix=b
+  [2] :/sub2
+  [2] :[
+      a = :/sub1
+      b = :/sub2
+  ]
+
+  $ echo contents4 > sub1/file
+  $ git commit -q -am "edit sub1"
+
+A new view containing all members of a cached one is computed on top of it,
+which adds the new commit to the cached one
+
+  $ josh-filter -s :[a=:/sub1,b=:/sub2,c=:/sub3] master --update refs/josh/filter/abc
+  [1] :prefix=b
+  [1] :prefix=c
+  [2] :/sub1
+  [2] :/sub2
+  [2] :/sub3
+  [2] :prefix=a
+  [3] :[
+      a = :/sub1
+      b = :/sub2
+  ]
+  [4] :[
+      a = :/sub1
+      b = :/sub2
+      c = :/sub3
+  ]
+  $ git log --pretty=%s refs/josh/filter/abc
+  edit sub1
+  add sub3
+  add sub2
+  add sub1
+  $ git ls-tree -r --name-only refs/josh/filter/abc
+  a/file
+  b/file
+  c/file
+
+The result is the same as without a cache
+
+  $ cd ${TESTTMP}
+  $ git clone -q libs libs2
+  $ cd libs2
+  $ josh-filter :[a=:/sub1,b=:/sub2,c=:/sub3] origin/master --update refs/josh/filter/abc
+  $ git rev-parse refs/josh/filter/abc
+  15f47e667cc2d29b1ae7819ca83a91b959479609
+  $ git -C ../libs rev-parse refs/josh/filter/abc
+  15f47e667cc2d29b1ae7819ca83a91b959479609
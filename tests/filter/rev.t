@@ -0,0 +1,114 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init libs 1> /dev/null
+  $ cd libs
+
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ echo contents2 > sub1/file2
+  $ git add sub1
+  $ git commit -m "add file2" 1> /dev/null
+
+  $ export BEFORE_MOVE=$(git rev-parse HEAD)
+
+  $ git mv sub1 sub2
+  $ git commit -m "mv sub1 sub2" 1> /dev/null
+
+  $ echo contents3 > sub2/file3
+  $ git add sub2
+  $ git commit -m "add file3" 1> /dev/null
+
+  $ josh-filter -s ":rev(${BEFORE_MOVE}:/sub1,_:/sub2)" master --update refs/josh/filter/master
+  [2] :/sub1
+  [3] :rev(81b10fb4984d20142cd275b89c91c346e536876a:/sub1,_:/sub2)
+  $ git log refs/josh/filter/master --graph --pretty=%s
+  * add file3
+  * add file2
+  * add file1
+  $ git ls-tree --name-only -r refs/josh/filter/master
+  file1
+  file2
+  file3
+  $ git ls-tree --name-only -r refs/josh/filter/master~2
+  file1
+
+The history of the selected range is the same as with the plain filter
+
+  $ git branch before_move ${BEFORE_MOVE}
+  $ josh-filter ":/sub1" before_move --update refs/josh/filter/sub1
+  $ git rev-parse refs/josh/filter/sub1 refs/josh/filter/master~1
+  d8388f5880393d255b371f1ed9b801d35620017e
+  d8388f5880393d255b371f1ed9b801d35620017e
+
+Commits not covered by any entry are left unfiltered if there is no "_" entry
+
+  $ josh-filter ":rev(${BEFORE_MOVE}:/sub1)" master --update refs/josh/filter/nodefault
+  $ git log refs/josh/filter/nodefault --graph --pretty=%s
+  * add file3
+  * mv sub1 sub2
+  * add file2
+  * add file1
+  $ git ls-tree --name-only -r refs/josh/filter/nodefault
+  sub2/file1
+  sub2/file2
+  sub2/file3
+
+The commit given as sha is filtered with its own entry, so using the commit that moved the
+directory removes all files in it
+
+  $ export MOVE=$(git rev-parse master~1)
+  $ josh-filter ":rev(${MOVE}:/sub1,_:/sub2)" master --update refs/josh/filter/move
+  $ git log refs/josh/filter/move --graph --pretty=%s --stat
+  * add file3
+  | 
+  |  file1 | 1 +
+  |  file2 | 1 +
+  |  file3 | 1 +
+  |  3 files changed, 3 insertions(+)
+  * mv sub1 sub2
+  | 
+  |  file1 | 1 -
+  |  file2 | 1 -
+  |  2 files changed, 2 deletions(-)
+  * add file2
+  | 
+  |  file2 | 1 +
+  |  1 file changed, 1 insertion(+)
+  * add file1
+    
+     file1 | 1 +
+     1 file changed, 1 insertion(+)
+
+  $ josh-filter -p ":rev(${BEFORE_MOVE}:/sub1,_:/sub2)"
+  :rev(81b10fb4984d20142cd275b89c91c346e536876a:/sub1,_:/sub2)
+  $ josh-filter -p ":rev(_:/sub2,${BEFORE_MOVE}:/sub1)"
+  ERROR: Filter ":rev": the "_" entry has to be the last one
+   --> 1:14
+    |
+  1 | :rev(_:/sub2,81b10fb4984d20142cd275b89c91c346e536876a:/sub1)
+    |              ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+  [1]
+
+Pushing uses the filter that applies to new commits
+
+  $ git checkout -q refs/josh/filter/master -b filtered
+  $ echo contents4 > file4
+  $ git add file4
+  $ git commit -m "add file4" 1> /dev/null
+  $ git checkout -q master
+  $ josh-filter ":rev(${BEFORE_MOVE}:/sub1,_:/sub2)" --reverse master --update refs/heads/filtered
+  $ git log --graph --pretty=%s master
+  * add file4
+  * add file3
+  * mv sub1 sub2
+  * add file2
+  * add file1
+  $ git ls-tree --name-only -r master
+  sub2/file1
+  sub2/file2
+  sub2/file3
+  sub2/file4
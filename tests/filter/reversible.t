@@ -0,0 +1,33 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ josh-filter -p ':/sub1:prefix=a'
+  a = :/sub1
+
+  $ josh-filter -p ':[a=:/sub1,b=:/sub2:INVERT]'
+  :[
+      a = :/sub1
+      b = :/sub2:INVERT
+  ]
+  Warning: the filter is not reversible
+    :INVERT: filter not reversible
+
+  $ josh-filter -p ':[a=:/sub1,:exclude[::*.md]:SQUASH]'
+  :[
+      a = :/sub1
+      :exclude[::*.md]:SQUASH
+  ]
+  Warning: the filter is not reversible
+    :SQUASH: filter not reversible
+
+  $ josh-filter -p ':subtract[:/a,:/b]:plugin=foo' 2>&1
+  :subtract[
+          :/a
+          :/b
+      ]:plugin=foo
+  Warning: the filter is not reversible
+    :subtract[:/a,:/b]: filter not reversible
+    :plugin=foo: plugin filters are not reversible
@@ -0,0 +1,69 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ for year in 2012 2013 2014 2015 2016; do
+  >   echo $year > file$year
+  >   git add file$year
+  >   GIT_COMMITTER_DATE="$year-06-01T12:00:00" git commit -q -m "commit $year"
+  > done
+
+  $ josh-filter -s :since=2014-01-01 master --update refs/josh/filter/since
+  [5] :since=2014-01-01
+  $ git log --pretty=%s --stat refs/josh/filter/since
+  commit 2016
+  
+   file2016 | 1 +
+   1 file changed, 1 insertion(+)
+  commit 2015
+  
+   file2015 | 1 +
+   1 file changed, 1 insertion(+)
+  commit 2014
+  
+   file2012 | 1 +
+   file2013 | 1 +
+   file2014 | 1 +
+   3 files changed, 3 insertions(+)
+
+  $ josh-filter ":since=$(git rev-parse HEAD~1)" master --update refs/josh/filter/since_sha
+  $ git log --pretty=%s refs/josh/filter/since_sha
+  commit 2016
+  commit 2015
+
+Pushed commits are placed on top of the full history
+
+  $ git checkout -q refs/josh/filter/since -b filtered
+  $ echo contents > new_file
+  $ git add new_file
+  $ git commit -q -m "add new_file"
+  $ josh-filter :since=2014-01-01 --reverse master --update refs/heads/filtered
+  $ git log --pretty=%s master
+  add new_file
+  commit 2016
+  commit 2015
+  commit 2014
+  commit 2013
+  commit 2012
+
+  $ josh-filter -p :since=2014
+  ERROR: Invalid cutoff for ":since": "2014"
+   --> 1:1
+    |
+  1 | :since=2014
+    | ^^^^^^^^^^^
+    |
+    = help: use a date like "2015-01-01" or a full commit id
+  [1]
+
+  $ josh-filter -p :since=2014-02-30
+  ERROR: Invalid cutoff for ":since": "2014-02-30"
+   --> 1:1
+    |
+  1 | :since=2014-02-30
+    | ^^^^^^^^^^^^^^^^^
+    |
+    = help: use a date like "2015-01-01" or a full commit id
+  [1]
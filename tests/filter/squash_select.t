@@ -0,0 +1,68 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+
+  $ for i in 1 2 3 4 5 6; do
+  >   echo $i > file$i
+  >   git add file$i
+  >   git commit -q -m "commit $i"
+  > done
+  $ git tag v1 HEAD~4
+  $ git tag -a -m "release 2" v2 HEAD~1
+
+Only the tagged commits are kept, each with the combined changes of the
+commits it replaces
+
+  $ josh-filter -s ":squash=refs/tags/*" master --update refs/josh/filter/release
+  [3] :squash=836f57b426c8d9d3f1e4fbf2195e02fe7be0700e,94ecfee93f0e7a33494b949306d995866f25cc6e
+  $ git log --pretty=%s --stat refs/josh/filter/release
+  commit 5
+  
+   file3 | 1 +
+   file4 | 1 +
+   file5 | 1 +
+   3 files changed, 3 insertions(+)
+  commit 2
+  
+   file1 | 1 +
+   file2 | 1 +
+   2 files changed, 2 insertions(+)
+
+New tags are picked up
+
+  $ git tag v3 master
+  $ josh-filter -s ":squash=refs/tags/*" master --update refs/josh/filter/release
+  [3] :squash=836f57b426c8d9d3f1e4fbf2195e02fe7be0700e,94ecfee93f0e7a33494b949306d995866f25cc6e
+  [4] :squash=836f57b426c8d9d3f1e4fbf2195e02fe7be0700e,94ecfee93f0e7a33494b949306d995866f25cc6e,af2666af6c951d2e2504181b5e9b22b748c28b17
+  $ git log --pretty=%s refs/josh/filter/release
+  commit 6
+  commit 5
+  commit 2
+
+Commits can also be listed explicitly
+
+  $ josh-filter ":squash=$(git rev-parse HEAD~3),$(git rev-parse v1)" master --update refs/josh/filter/listed
+  $ git log --pretty=%s refs/josh/filter/listed
+  commit 3
+  commit 2
+
+  $ josh-filter -p ":squash=v1"
+  ERROR: Invalid squash selector: "v1"
+   --> 1:1
+    |
+  1 | :squash=v1
+    | ^^^^^^^^^^
+    |
+    = help: use a full commit id or a ref pattern starting with "refs/"
+  [1]
+  $ josh-filter -p ":squash"
+  ERROR: Filter ":squash" requires an argument
+   --> 1:1
+    |
+  1 | :squash
+    | ^^^^^^^
+    |
+    = help: use "=" to provide the argument value: ":squash=refs/tags/*", where `refs/tags/*` selects the commits to keep, using ref patterns or full commit ids
+  [1]
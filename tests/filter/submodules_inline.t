@@ -0,0 +1,101 @@
+  $ export TESTTMP=${PWD}
+
+  $ cd ${TESTTMP}
+  $ git init -q libs 1> /dev/null
+  $ cd libs
+  $ mkdir sub1
+  $ echo contents1 > sub1/file1
+  $ git add sub1
+  $ git commit -m "add file1" 1> /dev/null
+
+  $ cd ${TESTTMP}
+  $ git init -q app 1> /dev/null
+  $ cd app
+  $ echo contents > app_file
+  $ git add app_file
+  $ git commit -m "init" 1> /dev/null
+  $ git fetch -q ../libs master:refs/josh/upstream/libs.git/refs/heads/master
+  $ git update-index --add --cacheinfo 160000,$(git rev-parse refs/josh/upstream/libs.git/refs/heads/master),libs
+  $ git commit -m "add libs" 1> /dev/null
+
+  $ cd ${TESTTMP}/libs
+  $ echo contents2 > sub1/file2
+  $ git add sub1
+  $ git commit -m "add file2" 1> /dev/null
+
+  $ cd ${TESTTMP}/app
+  $ git fetch -q ../libs master:refs/josh/upstream/libs.git/refs/heads/master
+  $ git update-index --cacheinfo 160000,$(git rev-parse refs/josh/upstream/libs.git/refs/heads/master),libs
+  $ git commit -m "bump libs" 1> /dev/null
+  $ echo contents3 > app_file
+  $ git add app_file
+  $ git commit -m "change app_file" 1> /dev/null
+
+  $ git ls-tree -r master
+  100644 blob 1cb5d64cdb55e3db2a8d6f00d596572b4cfa9d5c	app_file
+  160000 commit 81b10fb4984d20142cd275b89c91c346e536876a	libs
+
+  $ josh-filter -s :submodules master --update refs/josh/filter/master
+  [4] :submodules
+  $ git log --graph --pretty=%s refs/josh/filter/master
+  * change app_file
+  * bump libs
+  * add libs
+  * init
+  $ git ls-tree -r --name-only refs/josh/filter/master~2
+  app_file
+  libs/sub1/file1
+  $ git ls-tree -r --name-only refs/josh/filter/master
+  app_file
+  libs/sub1/file1
+  libs/sub1/file2
+
+  $ josh-filter -s :submodules:/libs master --update refs/josh/filter/libs
+  [3] :/libs
+  [4] :submodules
+  $ git log --graph --pretty=%s refs/josh/filter/libs
+  * bump libs
+  * add libs
+
+Pushing works as long as the submodule is not changed
+
+  $ git checkout -q refs/josh/filter/master -b filtered
+  $ echo contents4 > app_file
+  $ git add app_file
+  $ git commit -m "change app_file again" 1> /dev/null
+  $ josh-filter :submodules --reverse master --update refs/heads/filtered
+  $ git ls-tree -r master
+  100644 blob 288746e9035732a1fe600ee331de94e70f9639cb	app_file
+  160000 commit 81b10fb4984d20142cd275b89c91c346e536876a	libs
+  $ echo contents5 > libs/sub1/file1
+  $ git add libs
+  $ git commit -m "change libs" 1> /dev/null
+  $ josh-filter :submodules --reverse master --update refs/heads/filtered
+  ERROR: 
+  Can't apply "change libs" (587626d4f114ed904aeabf2e6ff45a4bdbb6a1d8)
+  can't push changes to submodule "libs"
+  [1]
+
+Pushing fails as well if the commit of the submodule is not available anymore
+
+  $ git update-ref -d refs/josh/upstream/libs.git/refs/heads/master
+  $ git reflog expire --expire=now --all
+  $ git gc -q --prune=now
+  $ git cat-file -e 81b10fb4984d20142cd275b89c91c346e536876a
+  [1]
+  $ josh-filter :submodules --reverse master --update refs/heads/filtered
+  Warning: reference refs/heads/filtered wasn't updated
+  ERROR: 
+  Can't apply "change libs" (587626d4f114ed904aeabf2e6ff45a4bdbb6a1d8)
+  commit 81b10fb4984d20142cd275b89c91c346e536876a of submodule "libs" is not available
+  [1]
+  $ git fetch -q ../libs master:refs/josh/upstream/libs.git/refs/heads/master
+
+Submodules pointing to commits that are not available can't be inlined
+
+  $ git checkout -q master
+  $ git update-index --add --cacheinfo 160000,1111111111111111111111111111111111111111,other
+  $ git commit -m "add other" 1> /dev/null
+  $ josh-filter :submodules master --update refs/josh/filter/master
+  ERROR: commit 1111111111111111111111111111111111111111 of submodule "other" is not available
+  [1]
@@ -19,12 +19,12 @@
 
   $ josh-filter -s :nosuch=filter master --update refs/josh/filtered
   ERROR: Invalid filter: ":nosuch"
-  
-  Note: use forward slash at the start of the filter if you're
-  trying to select a subdirectory:
-  
-    :/nosuch
-  
+   --> 1:1
+    |
+  1 | :nosuch=filter
+    | ^^^^^^^^^^^^^^
+    |
+    = help: use forward slash at the start of the filter if you're trying to select a subdirectory: ":/nosuch"
   [1]
 
   $ git ls-tree --name-only -r refs/josh/filtered
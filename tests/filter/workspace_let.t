@@ -0,0 +1,93 @@
+  $ export TERM=dumb
+  $ export RUST_LOG_STYLE=never
+
+  $ git init -q real_repo 1> /dev/null
+  $ cd real_repo
+
+  $ mkdir -p libs/lib1 libs/lib2 tools
+  $ echo contents1 > libs/lib1/file1
+  $ echo contents2 > libs/lib2/file2
+  $ echo contents3 > tools/file3
+  $ git add .
+  $ git commit -m "add libs" 1> /dev/null
+
+  $ mkdir ws
+  $ cat > ws/workspace.josh <<EOF
+  > # Shared library mapping
+  > let libs = :[
+  >     lib1 = :/libs/lib1
+  >     lib2 = :/libs/lib2
+  > ]
+  > let all = :[
+  >     :@libs
+  >     tools = :/tools
+  > ]
+  > 
+  > deps = :@all
+  > EOF
+  $ git add ws
+  $ git commit -m "add ws" 1> /dev/null
+
+  $ josh-filter :workspace=ws master --update refs/josh/master
+  $ git checkout -q refs/josh/master -b ws
+  $ tree
+  .
+  |-- deps
+  |   |-- lib1
+  |   |   `-- file1
+  |   |-- lib2
+  |   |   `-- file2
+  |   `-- tools
+  |       `-- file3
+  `-- workspace.josh
+  
+  4 directories, 4 files
+  $ josh-filter -p --file workspace.josh
+  let libs = :/libs:[
+      ::lib1/
+      ::lib2/
+  ]
+  let all = :[
+      :@libs
+      ::tools/
+  ]
+  
+  deps = :@all
+
+Pushing keeps the bindings
+
+  $ echo contents4 > deps/lib1/file4
+  $ git add deps
+  $ git commit -m "add file4" 1> /dev/null
+  $ josh-filter :workspace=ws --reverse master --update refs/heads/ws
+  $ git show master:ws/workspace.josh
+  # Shared library mapping
+  let libs = :/libs:[
+      ::lib1/
+      ::lib2/
+  ]
+  let all = :[
+      :@libs
+      ::tools/
+  ]
+  
+  deps = :@all
+  $ git ls-tree -r --name-only master
+  libs/lib1/file1
+  libs/lib1/file4
+  libs/lib2/file2
+  tools/file3
+  ws/workspace.josh
+
+Unknown bindings are rejected
+
+  $ cat > workspace.josh <<EOF
+  > a = :@missing
+  > EOF
+  $ josh-filter -p --file workspace.josh
+  ERROR: Unknown filter binding: ":@missing"
+   --> 1:5
+    |
+  1 | a = :@missing
+    |     ^^^^^^^^^
+  [1]
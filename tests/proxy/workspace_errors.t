@@ -60,24 +60,11 @@ Error: comment in the middle
   remote: response from upstream:        
   remote: 
   remote: Can't apply "add workspace file" (4f70c9a0179b1cae80148572c8dfc3ba1f2d43a2)        
-  remote: Invalid workspace:        
-  remote: ----        
+  remote: expected a filter such as `:/path` or a path        
   remote:  --> 6:1        
   remote:   |        
   remote: 6 | # comment 2        
-  remote:   | ^---        
-  remote:   |        
-  remote:   = expected EOI, filter_spec, or dst_path        
-  remote: 
-  remote: # comment        
-  remote: #        
-  remote: 
-  remote: 
-  remote: a/b = :/sub2        
-  remote: # comment 2        
-  remote: c = :/sub1        
-  remote: 
-  remote: ----        
+  remote:   | ^        
   remote: 
   remote: 
   remote: error: hook declined to update refs/heads/master        
@@ -99,19 +86,13 @@ Error in filter
   remote: response from upstream:        
   remote: 
   remote: Can't apply "add workspace file" (74128cac082e518bc3ddec183bb11b16856406cd)        
-  remote: Invalid workspace:        
-  remote: ----        
+  remote: expected a filter such as `:/path`        
   remote:  --> 1:9        
   remote:   |        
   remote: 1 | a/b = :b/sub2        
-  remote:   |         ^---        
+  remote:   |         ^        
   remote:   |        
-  remote:   = expected EOI, filter_group, filter_subdir, filter_nop, filter_presub, filter, or filter_noarg        
-  remote: 
-  remote: a/b = :b/sub2        
-  remote: c = :/sub1        
-  remote: 
-  remote: ----        
+  remote:   = help: use forward slash at the start of the filter if you're trying to select a subdirectory: ":/b/sub2"        
   remote: 
   remote: 
   remote: error: hook declined to update refs/heads/master        
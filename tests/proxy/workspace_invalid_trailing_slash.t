@@ -131,18 +131,11 @@ Flushed credential cache
   remote: response from upstream:
   remote:
   remote: Can't apply "mod workspace" (4e531443c5533e6d1b2503d0fad238cfc8491807)
-  remote: Invalid workspace:
-  remote: ----
+  remote: expected a list of filters
   remote:  --> 1:1
   remote:   |
   remote: 1 | a/ = :/sub1
-  remote:   | ^---
-  remote:   |
-  remote:   = expected workspace_file
-  remote:
-  remote: a/ = :/sub1
-  remote:
-  remote: ----
+  remote:   | ^
   remote:
   remote:
   remote: error: hook declined to update refs/heads/master